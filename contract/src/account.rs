@@ -0,0 +1,173 @@
+use crate::Account;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::{env, AccountId, Balance};
+use std::io;
+
+// Account record of the first layout
+#[derive(BorshDeserialize, BorshSerialize, Clone, PartialEq, Debug)]
+pub struct AccountV1 {
+    pub owner_id: AccountId,
+    pub balance: Balance,
+}
+
+// Tag of the current layout. Records written before versioning have no tag and
+// start with the length of the owner ID, between 2 and 64, so the tag is above 64.
+const ACCOUNT_V2_TAG: u8 = u8::MAX;
+
+// Bytes a record of the first layout grows by when rewritten in the current layout:
+// the tag, the empty multisig and unlock_at options and last_activity_at
+pub const V1_UPGRADE_STORAGE_USAGE: u64 = 1 + 1 + 1 + 8;
+
+// Account record in any stored layout
+#[derive(Clone, PartialEq, Debug)]
+pub enum VersionedAccount {
+    V1(AccountV1),
+    V2(Account),
+}
+
+impl BorshSerialize for VersionedAccount {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Self::V1(account) => account.serialize(writer),
+            Self::V2(account) => {
+                ACCOUNT_V2_TAG.serialize(writer)?;
+                account.serialize(writer)
+            }
+        }
+    }
+}
+
+impl BorshDeserialize for VersionedAccount {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        match buf.first() {
+            Some(&ACCOUNT_V2_TAG) => {
+                *buf = &buf[1..];
+                Ok(Self::V2(Account::deserialize(buf)?))
+            }
+            // Records written before versioning have no tag
+            _ => Ok(Self::V1(AccountV1::deserialize(buf)?)),
+        }
+    }
+}
+
+impl From<VersionedAccount> for Account {
+    // Upgrade an account record of any layout to the current one. Activity
+    // before the upgrade is unknown, so it is treated as never active.
+    fn from(account: VersionedAccount) -> Self {
+        match account {
+            VersionedAccount::V1(account) => Account {
+                owner_id: account.owner_id,
+                balance: account.balance,
+                multisig: None,
+                unlock_at: None,
+                last_activity_at: 0,
+            },
+            VersionedAccount::V2(account) => account,
+        }
+    }
+}
+
+// Map of account records that upgrades them on read and writes the current layout
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AccountMap(LookupMap<String, VersionedAccount>);
+
+impl AccountMap {
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self(LookupMap::new(prefix))
+    }
+
+    pub fn get(&self, account_name: &String) -> Option<Account> {
+        self.0.get(account_name).map(Account::from)
+    }
+
+    // Write an account record, marking the account as active.
+    // Rewriting a record of the first layout grows it by V1_UPGRADE_STORAGE_USAGE
+    // bytes. The growth is covered by the contract's own balance, like the rest of
+    // the records written before versioning: owners paid for the old size, and the
+    // rewrite also happens in calls they take no part in, such as incoming transfers.
+    pub fn insert(&mut self, account_name: &String, account: &Account) {
+        let mut account = account.clone();
+        account.last_activity_at = env::block_timestamp();
+        self.0.insert(account_name, &VersionedAccount::V2(account));
+    }
+
+    pub fn remove(&mut self, account_name: &String) -> Option<Account> {
+        self.0.remove(account_name).map(Account::from)
+    }
+
+    pub fn contains_key(&self, account_name: &String) -> bool {
+        self.0.contains_key(account_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use near_sdk::test_utils::accounts;
+
+    #[test]
+    fn test_read_unversioned_account() {
+        let bytes = AccountV1 {
+            owner_id: accounts(1),
+            balance: 100,
+        }
+        .try_to_vec()
+        .unwrap();
+
+        let account = Account::from(VersionedAccount::try_from_slice(&bytes).unwrap());
+        assert_eq!(account.owner_id, accounts(1));
+        assert_eq!(account.balance, 100);
+        assert_eq!(account.multisig, None);
+    }
+
+    #[test]
+    fn test_read_unversioned_account_with_shortest_owner_id() {
+        let owner_id = AccountId::new_unchecked("ab".to_owned());
+        let bytes = AccountV1 {
+            owner_id: owner_id.clone(),
+            balance: 100,
+        }
+        .try_to_vec()
+        .unwrap();
+
+        assert_eq!(
+            VersionedAccount::try_from_slice(&bytes).unwrap(),
+            VersionedAccount::V1(AccountV1 {
+                owner_id,
+                balance: 100,
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_versioned_account() {
+        let account = Account {
+            owner_id: accounts(1),
+            balance: 100,
+            multisig: None,
+            unlock_at: Some(10),
+            last_activity_at: 20,
+        };
+        let bytes = VersionedAccount::V2(account.clone()).try_to_vec().unwrap();
+
+        assert_eq!(
+            VersionedAccount::try_from_slice(&bytes).unwrap(),
+            VersionedAccount::V2(account)
+        );
+    }
+
+    #[test]
+    fn test_upgrade_storage_usage() {
+        let account = AccountV1 {
+            owner_id: accounts(1),
+            balance: 100,
+        };
+        let old_len = account.try_to_vec().unwrap().len();
+        let new_len = VersionedAccount::V2(Account::from(VersionedAccount::V1(account)))
+            .try_to_vec()
+            .unwrap()
+            .len();
+        assert_eq!((new_len - old_len) as u64, V1_UPGRADE_STORAGE_USAGE);
+    }
+}
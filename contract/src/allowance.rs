@@ -0,0 +1,330 @@
+use crate::storage::assert_at_least_one_yocto;
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Promise};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Allowance {
+    // Account ID allowed to spend from the account
+    pub spender_id: AccountId,
+
+    // Remaining amount the spender may spend
+    pub amount: U128,
+
+    // Timestamp in nanoseconds after which the allowance cannot be used
+    pub expires_at: Option<U64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Allow spender to transfer or withdraw up to amount from an account
+    #[payable]
+    pub fn approve(
+        &mut self,
+        account_name: String,
+        spender_id: AccountId,
+        amount: U128,
+        expires_at: Option<U64>,
+    ) {
+        assert_at_least_one_yocto();
+        let owner_id = self.internal_assert_allowance_owner(&account_name);
+        require!(spender_id != owner_id, "Cannot approve the account owner");
+        self.internal_assert_not_guarded(&account_name);
+
+        // Owner pays for the allowance storage
+        let initial_storage_usage = env::storage_usage();
+        let mut allowances = self.allowances.get(&account_name).unwrap_or_default();
+        allowances.retain(|allowance| allowance.spender_id != spender_id);
+        allowances.push(Allowance {
+            spender_id,
+            amount,
+            expires_at,
+        });
+        self.allowances.insert(&account_name, &allowances);
+        self.internal_charge_storage_with_deposit(
+            &owner_id,
+            initial_storage_usage,
+            env::attached_deposit() - 1,
+        );
+    }
+
+    // Remove allowance of spender from an account
+    #[payable]
+    pub fn revoke(&mut self, account_name: String, spender_id: AccountId) {
+        assert_one_yocto();
+        let owner_id = self.internal_assert_allowance_owner(&account_name);
+        self.internal_remove_allowance(&owner_id, &account_name, &spender_id);
+    }
+
+    // Transfer tokens from an account using the caller's allowance
+    pub fn transfer_from(
+        &mut self,
+        sender_account_name: String,
+        receiver_account_name: String,
+        amount: U128,
+    ) {
+        self.internal_use_allowance(&sender_account_name, amount.into());
+
+        let (sender_account, receiver_account, transfer_fee) = self
+            .internal_prepare_transfer(
+                &sender_account_name,
+                &receiver_account_name,
+                amount.into(),
+                None,
+            )
+            .unwrap_or_else(|err| panic!("{}", err));
+        self.internal_collect_transfer_fee(&sender_account.owner_id, transfer_fee);
+
+        // Update accounts in storage
+        self.accounts.insert(&sender_account_name, &sender_account);
+        self.accounts
+            .insert(&receiver_account_name, &receiver_account);
+    }
+
+    // Withdraw tokens from an account to the caller using the caller's allowance
+    #[payable]
+    pub fn withdraw_from(&mut self, account_name: String, amount: U128) -> Promise {
+        assert_one_yocto();
+        self.internal_use_allowance(&account_name, amount.into());
+
+        // Subtract amount from account balance
+        let mut account = self.accounts.get(&account_name).unwrap();
+        account.balance = account
+            .balance
+            .checked_sub(amount.into())
+            .unwrap_or_else(|| panic!("Balance overflow"));
+        self.accounts.insert(&account_name, &account);
+
+        self.internal_ft_transfer(account_name, env::predecessor_account_id(), amount, None)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get allowance of spender on an account
+    pub fn get_allowance(&self, account_name: String, spender_id: AccountId) -> Option<Allowance> {
+        self.allowances.get(&account_name).and_then(|allowances| {
+            allowances
+                .into_iter()
+                .find(|allowance| allowance.spender_id == spender_id)
+        })
+    }
+
+    // Get all allowances on an account
+    pub fn get_allowances(&self, account_name: String) -> Vec<Allowance> {
+        self.allowances.get(&account_name).unwrap_or_default()
+    }
+}
+
+impl Contract {
+    // Check that the caller owns a single-signature account and return the owner
+    fn internal_assert_allowance_owner(&self, account_name: &String) -> AccountId {
+        let account = self
+            .accounts
+            .get(account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"));
+        require!(
+            account.owner_id == env::signer_account_id(),
+            "Unauthorized access to account"
+        );
+        require!(
+            account.multisig.is_none(),
+            "Multi-signature account requires a proposal"
+        );
+        account.owner_id
+    }
+
+    // Subtract amount from the caller's allowance on an account
+    fn internal_use_allowance(&mut self, account_name: &String, amount: Balance) {
+        let spender_id = env::predecessor_account_id();
+        let account = self
+            .accounts
+            .get(account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"));
+        require!(
+            account.multisig.is_none(),
+            "Multi-signature account requires a proposal"
+        );
+
+        let mut allowances = self.allowances.get(account_name).unwrap_or_default();
+        let allowance = allowances
+            .iter_mut()
+            .find(|allowance| allowance.spender_id == spender_id)
+            .unwrap_or_else(|| panic!("Unauthorized access to account"));
+        if let Some(expires_at) = allowance.expires_at {
+            require!(
+                env::block_timestamp() <= expires_at.0,
+                "Allowance has expired"
+            );
+        }
+        allowance.amount = Balance::from(allowance.amount)
+            .checked_sub(amount)
+            .unwrap_or_else(|| panic!("Insufficient allowance"))
+            .into();
+        let is_used_up = allowance.amount.0 == 0;
+        self.internal_before_outflow(account_name, &account, amount);
+
+        // Used up allowance is removed to free its storage
+        if is_used_up {
+            self.internal_remove_allowance(&account.owner_id, account_name, &spender_id);
+        } else {
+            self.allowances.insert(account_name, &allowances);
+        }
+    }
+
+    // Remove allowance and refund its storage to the account owner
+    fn internal_remove_allowance(
+        &mut self,
+        owner_id: &AccountId,
+        account_name: &String,
+        spender_id: &AccountId,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        let mut allowances = self.allowances.get(account_name).unwrap_or_default();
+        allowances.retain(|allowance| &allowance.spender_id != spender_id);
+        if allowances.is_empty() {
+            self.allowances.remove(account_name);
+        } else {
+            self.allowances.insert(account_name, &allowances);
+        }
+        self.internal_refund_storage(owner_id, initial_storage_usage);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        for account_id in [accounts(1), accounts(3)] {
+            setup_user(&mut contract, &account_id, &[account_id.as_str()]);
+        }
+        contract.internal_deposit(accounts(1).to_string(), 100.into());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.approve(
+            accounts(1).to_string(),
+            accounts(4),
+            50.into(),
+            Some(100.into()),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_approve() {
+        let contract = setup_contract();
+
+        assert_eq!(
+            contract.get_allowance(accounts(1).to_string(), accounts(4)),
+            Some(Allowance {
+                spender_id: accounts(4),
+                amount: 50.into(),
+                expires_at: Some(100.into()),
+            })
+        );
+        assert!(contract
+            .get_allowance(accounts(1).to_string(), accounts(5))
+            .is_none());
+    }
+
+    #[test]
+    fn test_transfer_from() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        contract.transfer_from(accounts(1).to_string(), accounts(3).to_string(), 30.into());
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            70.into()
+        );
+        assert_eq!(
+            contract.get_balance(accounts(3).to_string()).unwrap(),
+            30.into()
+        );
+        assert_eq!(
+            contract
+                .get_allowance(accounts(1).to_string(), accounts(4))
+                .unwrap()
+                .amount,
+            20.into()
+        );
+    }
+
+    #[test]
+    fn test_withdraw_from_uses_up_allowance() {
+        let mut contract = setup_contract();
+        let storage_balance = contract.storage_balance_of(accounts(1)).unwrap();
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_from(accounts(1).to_string(), 50.into());
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            50.into()
+        );
+        assert!(contract
+            .get_allowance(accounts(1).to_string(), accounts(4))
+            .is_none());
+        assert!(
+            contract
+                .storage_balance_of(accounts(1))
+                .unwrap()
+                .available
+                .0
+                > storage_balance.available.0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient allowance")]
+    fn test_transfer_from_insufficient_allowance() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        contract.transfer_from(accounts(1).to_string(), accounts(3).to_string(), 51.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Allowance has expired")]
+    fn test_transfer_from_expired_allowance() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context.block_timestamp(101).build());
+        contract.transfer_from(accounts(1).to_string(), accounts(3).to_string(), 1.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access to account")]
+    fn test_transfer_from_after_revoke() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.revoke(accounts(1).to_string(), accounts(4));
+
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        contract.transfer_from(accounts(1).to_string(), accounts(3).to_string(), 1.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access to account")]
+    fn test_approve_unauthorized_access() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.attached_deposit(1).build());
+        contract.approve(accounts(1).to_string(), accounts(3), 1.into(), None);
+    }
+}
@@ -0,0 +1,398 @@
+use crate::event::emit_event;
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum EscrowStatus {
+    // Funds are held by the contract
+    Pending,
+
+    // Funds were paid to the receiver account
+    Released,
+
+    // Funds were returned to the sender account
+    Refunded,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Escrow {
+    pub id: U64,
+
+    // Account the funds were taken from
+    pub from_account: String,
+
+    // Owner of the sender account who paid for the escrow storage
+    pub sender_id: AccountId,
+
+    // Account the funds are released to
+    pub to_account_name: String,
+
+    pub amount: U128,
+
+    // Account ID allowed to release the funds besides the sender
+    pub arbiter: AccountId,
+
+    // Timestamp in nanoseconds after which the funds can be refunded
+    pub deadline: U64,
+
+    // Settled escrows are kept until the sender removes them
+    pub status: EscrowStatus,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Move funds from an account into escrow for another account
+    #[payable]
+    pub fn create_escrow(
+        &mut self,
+        from_account: String,
+        to_account_name: String,
+        amount: U128,
+        arbiter: AccountId,
+        deadline: U64,
+    ) -> U64 {
+        require!(!self.paused.transfers, "Transfers are paused");
+        require!(amount.0 > 0, "Escrow amount must be positive");
+        require!(
+            deadline.0 > env::block_timestamp(),
+            "Deadline must be in the future"
+        );
+        require!(
+            self.accounts.get(&to_account_name).is_some(),
+            "Receiver account does not exist"
+        );
+        require!(
+            from_account != to_account_name,
+            "Cannot transfer to the same account"
+        );
+
+        // Hold the amount in escrow
+        self.internal_withdraw_from_account(&from_account, amount.into());
+
+        // Sender pays for the escrow storage
+        let initial_storage_usage = env::storage_usage();
+        let id = self.next_escrow_id;
+        self.next_escrow_id += 1;
+        let escrow = Escrow {
+            id: id.into(),
+            from_account,
+            sender_id: env::signer_account_id(),
+            to_account_name,
+            amount,
+            arbiter,
+            deadline,
+            status: EscrowStatus::Pending,
+        };
+        self.escrows.insert(&id, &escrow);
+        for account_name in [&escrow.from_account, &escrow.to_account_name] {
+            let mut account_escrows = self.account_escrows.get(account_name).unwrap_or_default();
+            account_escrows.push(id);
+            self.account_escrows.insert(account_name, &account_escrows);
+        }
+        self.internal_charge_storage_with_deposit(
+            &escrow.sender_id,
+            initial_storage_usage,
+            env::attached_deposit(),
+        );
+        id.into()
+    }
+
+    // Release escrowed funds to the receiver account
+    pub fn release(&mut self, escrow_id: U64) {
+        require!(!self.paused.transfers, "Transfers are paused");
+        let escrow = self.internal_get_pending_escrow(escrow_id.0);
+        let signer_id = env::signer_account_id();
+        require!(
+            signer_id == escrow.sender_id || signer_id == escrow.arbiter,
+            "Unauthorized access to escrow"
+        );
+
+        // Receiver gets the amount minus the transfer fee, as with transfer
+        let mut receiver_account = self
+            .accounts
+            .get(&escrow.to_account_name)
+            .unwrap_or_else(|| panic!("Receiver account does not exist"));
+        let transfer_fee = self
+            .internal_credit_transfer(&escrow.sender_id, &mut receiver_account, escrow.amount.0)
+            .unwrap_or_else(|err| panic!("{}", err));
+        self.internal_collect_transfer_fee(&escrow.sender_id, transfer_fee);
+        self.accounts
+            .insert(&escrow.to_account_name, &receiver_account);
+
+        self.internal_settle_escrow(escrow, EscrowStatus::Released);
+    }
+
+    // Return escrowed funds to the sender account after the deadline
+    pub fn refund(&mut self, escrow_id: U64) {
+        require!(!self.paused.transfers, "Transfers are paused");
+        let escrow = self.internal_get_pending_escrow(escrow_id.0);
+        let signer_id = env::signer_account_id();
+        require!(
+            signer_id == escrow.sender_id || signer_id == escrow.arbiter,
+            "Unauthorized access to escrow"
+        );
+        require!(
+            env::block_timestamp() > escrow.deadline.0,
+            "Escrow deadline has not passed"
+        );
+
+        self.internal_refund(&escrow.from_account, escrow.amount.0);
+        self.internal_settle_escrow(escrow, EscrowStatus::Refunded);
+    }
+
+    // Remove a settled escrow from both accounts and refund its storage to the sender
+    pub fn remove_escrow(&mut self, escrow_id: U64) {
+        let escrow = self
+            .escrows
+            .get(&escrow_id.0)
+            .unwrap_or_else(|| panic!("Escrow does not exist"));
+        require!(
+            env::signer_account_id() == escrow.sender_id,
+            "Unauthorized access to escrow"
+        );
+        require!(
+            escrow.status != EscrowStatus::Pending,
+            "Escrow is not settled"
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        self.escrows.remove(&escrow.id.0);
+        for account_name in [&escrow.from_account, &escrow.to_account_name] {
+            let mut account_escrows = self.account_escrows.get(account_name).unwrap_or_default();
+            account_escrows.retain(|id| *id != escrow.id.0);
+            if account_escrows.is_empty() {
+                self.account_escrows.remove(account_name);
+            } else {
+                self.account_escrows.insert(account_name, &account_escrows);
+            }
+        }
+        self.internal_refund_storage(&escrow.sender_id, initial_storage_usage);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get escrow by ID
+    pub fn get_escrow(&self, escrow_id: U64) -> Option<Escrow> {
+        self.escrows.get(&escrow_id.0)
+    }
+
+    // Get all escrows an account sends or receives
+    pub fn get_escrows(&self, account_name: String) -> Vec<Escrow> {
+        self.account_escrows
+            .get(&account_name)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|id| self.escrows.get(id))
+            .collect()
+    }
+}
+
+impl Contract {
+    fn internal_get_pending_escrow(&self, escrow_id: u64) -> Escrow {
+        let escrow = self
+            .escrows
+            .get(&escrow_id)
+            .unwrap_or_else(|| panic!("Escrow does not exist"));
+        require!(
+            escrow.status == EscrowStatus::Pending,
+            "Escrow is already settled"
+        );
+        escrow
+    }
+
+    // Record the final status of an escrow and log it
+    fn internal_settle_escrow(&mut self, mut escrow: Escrow, status: EscrowStatus) {
+        escrow.status = status;
+        self.escrows.insert(&escrow.id.0, &escrow);
+        emit_event("settle_escrow", json!({ "escrow": escrow }));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{
+        test_utils::{accounts, get_logs},
+        testing_env, Balance,
+    };
+
+    const DEADLINE: u64 = 1_000;
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        for account_id in [accounts(1), accounts(3)] {
+            setup_user(&mut contract, &account_id, &[account_id.as_str()]);
+        }
+        contract.internal_deposit(accounts(1).to_string(), 100.into());
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.create_escrow(
+            accounts(1).to_string(),
+            accounts(3).to_string(),
+            100.into(),
+            accounts(4),
+            DEADLINE.into(),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_create_escrow() {
+        let contract = setup_contract();
+
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            0.into()
+        );
+        assert_eq!(
+            contract.get_escrows(accounts(3).to_string()),
+            vec![contract.get_escrow(0.into()).unwrap()]
+        );
+        assert_eq!(
+            contract.get_escrow(0.into()).unwrap().status,
+            EscrowStatus::Pending
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot unregister the user with open escrows or transfers")]
+    fn test_unregister_with_pending_escrow() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.attached_deposit(1).build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    fn test_unregister_after_release() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context.build());
+        contract.release(0.into());
+
+        testing_env!(context
+            .attached_deposit(1)
+            .signer_account_id(accounts(3))
+            .predecessor_account_id(accounts(3))
+            .build());
+        assert!(contract.storage_unregister(Some(true)));
+    }
+
+    #[test]
+    fn test_release_by_arbiter() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        contract.release(0.into());
+        assert_eq!(
+            contract.get_balance(accounts(3).to_string()).unwrap(),
+            99.into()
+        );
+
+        let log = get_logs().pop().unwrap();
+        assert!(log.contains("\"settle_escrow\"") && log.contains("\"Released\""));
+        assert_eq!(
+            contract.get_escrows(accounts(1).to_string())[0].status,
+            EscrowStatus::Released
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access to escrow")]
+    fn test_release_by_receiver() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.release(0.into());
+    }
+
+    #[test]
+    fn test_refund_after_deadline() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(DEADLINE + 1).build());
+        contract.refund(0.into());
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            100.into()
+        );
+        assert_eq!(
+            contract.get_escrow(0.into()).unwrap().status,
+            EscrowStatus::Refunded
+        );
+    }
+
+    #[test]
+    fn test_remove_escrow() {
+        let mut contract = setup_contract();
+        let storage_balance = contract.storage_balance_of(accounts(1)).unwrap();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.release(0.into());
+        let initial_storage_usage = env::storage_usage();
+        contract.remove_escrow(0.into());
+        assert!(contract.get_escrow(0.into()).is_none());
+        assert!(contract.get_escrows(accounts(1).to_string()).is_empty());
+        assert!(contract.get_escrows(accounts(3).to_string()).is_empty());
+
+        // Escrow storage is refunded to the sender
+        assert_eq!(
+            contract
+                .storage_balance_of(accounts(1))
+                .unwrap()
+                .available
+                .0
+                - storage_balance.available.0,
+            Balance::from(initial_storage_usage - env::storage_usage()) * env::storage_byte_cost()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow is not settled")]
+    fn test_remove_pending_escrow() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.remove_escrow(0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow deadline has not passed")]
+    fn test_refund_before_deadline() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.refund(0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow is already settled")]
+    fn test_refund_after_release() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.release(0.into());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(DEADLINE + 1).build());
+        contract.refund(0.into());
+    }
+}
@@ -0,0 +1,18 @@
+use near_sdk::log;
+use near_sdk::serde_json::{json, Value};
+
+const EVENT_STANDARD: &str = "vault";
+const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+// Log an event in the NEP-297 format
+pub fn emit_event(event: &str, data: Value) {
+    log!(
+        "EVENT_JSON:{}",
+        json!({
+            "standard": EVENT_STANDARD,
+            "version": EVENT_STANDARD_VERSION,
+            "event": event,
+            "data": [data],
+        })
+    );
+}
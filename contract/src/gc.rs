@@ -0,0 +1,373 @@
+use crate::escrow::EscrowStatus;
+use crate::event::emit_event;
+use crate::role::Role;
+use crate::storage::MAX_ACCOUNTS_PER_UNREGISTER;
+use crate::{Contract, ContractExt};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde_json::json;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Promise};
+
+// Default duration in nanoseconds without activity before a user can be removed (365 days)
+pub const DEFAULT_INACTIVITY_PERIOD: u64 = 365 * 24 * 60 * 60 * 1_000_000_000;
+
+#[near_bindgen]
+impl Contract {
+    // Set the duration without activity after which a user can be removed
+    #[payable]
+    pub fn set_inactivity_period(&mut self, inactivity_period: U64) {
+        assert_one_yocto();
+        self.internal_assert_role(Role::Admin);
+        require!(inactivity_period.0 > 0, "Invalid inactivity period");
+        self.inactivity_period = inactivity_period.0;
+    }
+
+    // Check up to limit users, continuing where the previous call stopped, and
+    // remove those that are inactive with only empty accounts, refunding their
+    // storage balance. Return the number of removed users.
+    #[payable]
+    pub fn gc_inactive(&mut self, limit: u32) -> u32 {
+        assert_one_yocto();
+        self.internal_assert_role(Role::Admin);
+
+        let mut removed_count = 0;
+        let limit = limit.min(self.user_activity.len() as u32);
+        for _ in 0..limit {
+            if self.gc_cursor >= self.user_activity.len() {
+                self.gc_cursor = 0;
+            }
+            let account_id = self
+                .user_activity
+                .keys_as_vector()
+                .get(self.gc_cursor)
+                .unwrap();
+            if !self.internal_is_inactive(&account_id) {
+                self.gc_cursor += 1;
+                continue;
+            }
+
+            // Removal moves the last user to the cursor, so the cursor stays
+            let accounts =
+                self.internal_remove_user_accounts(&account_id, MAX_ACCOUNTS_PER_UNREGISTER);
+            let refund = self.internal_unregister(&account_id);
+            if refund > 0 {
+                Promise::new(account_id.clone()).transfer(refund);
+            }
+            emit_event(
+                "remove_inactive_user",
+                json!({ "account_id": account_id, "accounts": accounts, "refund": U128(refund) }),
+            );
+            removed_count += 1;
+        }
+        removed_count
+    }
+
+    // Start tracking activity of users registered before it was tracked, so that
+    // gc_inactive reaches them. Return the number of newly tracked users.
+    #[payable]
+    pub fn track_user_activity(&mut self, account_ids: Vec<AccountId>) -> u32 {
+        assert_one_yocto();
+        self.internal_assert_role(Role::Admin);
+
+        let mut tracked_count = 0;
+        for account_id in account_ids {
+            if self.user_accounts.contains_key(&account_id)
+                && self.user_activity.get(&account_id).is_none()
+            {
+                self.user_activity
+                    .insert(&account_id, &self.activity_tracked_since);
+                tracked_count += 1;
+            }
+        }
+        tracked_count
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get the duration without activity after which a user can be removed
+    pub fn get_inactivity_period(&self) -> U64 {
+        self.inactivity_period.into()
+    }
+
+    // Get the timestamp of the last storage or account creation activity of a user
+    pub fn get_user_activity(&self, account_id: AccountId) -> Option<U64> {
+        self.user_activity
+            .get(&account_id)
+            .or_else(|| {
+                self.user_accounts
+                    .contains_key(&account_id)
+                    .then_some(self.activity_tracked_since)
+            })
+            .map(U64)
+    }
+}
+
+impl Contract {
+    // Record activity of a registered user
+    pub fn internal_touch_user(&mut self, account_id: &AccountId) {
+        if self.user_accounts.contains_key(account_id) {
+            self.user_activity
+                .insert(account_id, &env::block_timestamp());
+        }
+    }
+
+    // Whether neither the user nor any of their accounts was active for the
+    // inactivity period, and no tokens or referral reward would be lost by
+    // removing the user
+    fn internal_is_inactive(&self, account_id: &AccountId) -> bool {
+        let now = env::block_timestamp();
+        let is_expired = |timestamp: u64| now.saturating_sub(timestamp) >= self.inactivity_period;
+        if !self.user_activity.get(account_id).is_some_and(is_expired)
+            || self.internal_has_referral_reward(account_id)
+        {
+            return false;
+        }
+
+        // Users with more accounts than one call removes unregister themselves
+        if self.user_accounts.len(account_id) > MAX_ACCOUNTS_PER_UNREGISTER {
+            return false;
+        }
+        self.user_accounts
+            .get(account_id, 0, MAX_ACCOUNTS_PER_UNREGISTER)
+            .unwrap_or_default()
+            .iter()
+            .all(|account_name| {
+                let account = self.accounts.get(account_name).unwrap();
+                account.balance == 0
+                    && is_expired(account.last_activity_at)
+                    && !self.internal_has_open_transfers(account_name)
+                    && !self.internal_has_pending_withdrawals(account_name)
+            })
+    }
+
+    // Check whether an account takes part in a pending escrow or an open
+    // vesting, HTLC or standing order
+    pub fn internal_has_open_transfers(&self, account_name: &String) -> bool {
+        self.internal_has_pending_escrow(account_name)
+            || self.account_open_transfers.contains_key(account_name)
+    }
+
+    // Count a vesting, HTLC or standing order between two accounts
+    pub fn internal_add_open_transfer(
+        &mut self,
+        sender_account_name: &String,
+        receiver_account_name: &String,
+    ) {
+        for account_name in [sender_account_name, receiver_account_name] {
+            let count = self.account_open_transfers.get(account_name).unwrap_or(0);
+            self.account_open_transfers
+                .insert(account_name, &(count + 1));
+        }
+    }
+
+    // Stop counting a removed vesting, HTLC or standing order
+    pub fn internal_remove_open_transfer(
+        &mut self,
+        sender_account_name: &String,
+        receiver_account_name: &String,
+    ) {
+        for account_name in [sender_account_name, receiver_account_name] {
+            match self.account_open_transfers.get(account_name) {
+                Some(count) if count > 1 => {
+                    self.account_open_transfers
+                        .insert(account_name, &(count - 1));
+                }
+                _ => {
+                    self.account_open_transfers.remove(account_name);
+                }
+            }
+        }
+    }
+
+    fn internal_has_pending_escrow(&self, account_name: &String) -> bool {
+        self.account_escrows
+            .get(account_name)
+            .unwrap_or_default()
+            .iter()
+            .any(|escrow_id| {
+                self.escrows
+                    .get(escrow_id)
+                    .is_some_and(|escrow| escrow.status == EscrowStatus::Pending)
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::referral::ReferralStats;
+    use crate::standing_order::KEEPER_TIP;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    const PERIOD: u64 = 1_000;
+
+    // Register accounts(3) with an empty account and accounts(4) with a funded one
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.set_inactivity_period(PERIOD.into());
+
+        setup_user(&mut contract, &accounts(3), &["empty"]);
+        setup_user(&mut contract, &accounts(4), &["funded"]);
+        contract.internal_deposit("funded".to_owned(), 10.into());
+        contract
+    }
+
+    fn gc_inactive(contract: &mut Contract, block_timestamp: u64) -> u32 {
+        testing_env!(get_context(accounts(1))
+            .storage_usage(env::storage_usage())
+            .block_timestamp(block_timestamp)
+            .attached_deposit(1)
+            .build());
+        contract.gc_inactive(10)
+    }
+
+    #[test]
+    fn test_gc_inactive() {
+        let mut contract = setup_contract();
+
+        assert_eq!(gc_inactive(&mut contract, PERIOD - 1), 0);
+        assert_eq!(gc_inactive(&mut contract, PERIOD), 1);
+        assert!(contract.get_accounts(accounts(3), None, None).is_none());
+        assert!(contract.get_account("empty".to_owned()).is_none());
+        assert!(contract.storage_balance_of(accounts(3)).is_none());
+        assert!(contract.get_user_activity(accounts(3)).is_none());
+
+        // User with tokens is kept
+        assert_eq!(
+            contract.get_accounts(accounts(4), None, None),
+            Some(vec!["funded".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_gc_inactive_after_activity() {
+        let mut contract = setup_contract();
+
+        // Storage deposit restarts the inactivity period
+        testing_env!(get_context(accounts(3))
+            .storage_usage(env::storage_usage())
+            .block_timestamp(PERIOD / 2)
+            .attached_deposit(1)
+            .build());
+        contract.storage_deposit(None, None);
+        assert_eq!(
+            contract.get_user_activity(accounts(3)),
+            Some((PERIOD / 2).into())
+        );
+
+        assert_eq!(gc_inactive(&mut contract, PERIOD), 0);
+        assert_eq!(gc_inactive(&mut contract, PERIOD / 2 + PERIOD), 1);
+    }
+
+    #[test]
+    fn test_gc_user_registered_before_tracking() {
+        let mut contract = setup_contract();
+
+        // User registered before the upgrade has no activity entry
+        contract.user_activity.remove(&accounts(3));
+        assert_eq!(gc_inactive(&mut contract, PERIOD), 0);
+        assert_eq!(
+            contract
+                .storage_balance_of(accounts(3))
+                .unwrap()
+                .last_activity_at,
+            Some(contract.activity_tracked_since.into())
+        );
+
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        assert_eq!(
+            contract.track_user_activity(vec![accounts(3), accounts(4), accounts(5)]),
+            1
+        );
+        assert_eq!(gc_inactive(&mut contract, PERIOD), 1);
+        assert!(contract.get_accounts(accounts(3), None, None).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_gc_inactive_unauthorized_access() {
+        let mut contract = setup_contract();
+
+        testing_env!(get_context(accounts(3)).attached_deposit(1).build());
+        contract.gc_inactive(10);
+    }
+
+    // Call as the owner of the funded account with enough deposit for any storage
+    fn set_funded_context() {
+        testing_env!(get_context(accounts(4))
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+    }
+
+    #[test]
+    fn test_gc_keeps_user_with_referral_reward() {
+        let mut contract = setup_contract();
+        contract.referral_stats.insert(
+            &accounts(3),
+            &ReferralStats {
+                referred_count: 1,
+                total_reward: 5.into(),
+                available_reward: 5.into(),
+            },
+        );
+
+        assert_eq!(gc_inactive(&mut contract, PERIOD), 0);
+        assert!(contract.get_accounts(accounts(3), None, None).is_some());
+    }
+
+    #[test]
+    fn test_gc_keeps_users_with_vesting() {
+        let mut contract = setup_contract();
+        set_funded_context();
+        contract.create_vesting(
+            "funded".to_owned(),
+            "empty".to_owned(),
+            10.into(),
+            0.into(),
+            0.into(),
+            (PERIOD * 10).into(),
+        );
+
+        // Both the emptied sender and the receiver are kept
+        assert_eq!(gc_inactive(&mut contract, PERIOD), 0);
+    }
+
+    #[test]
+    fn test_gc_keeps_users_with_htlc() {
+        let mut contract = setup_contract();
+        set_funded_context();
+        contract.lock_htlc(
+            "funded".to_owned(),
+            "empty".to_owned(),
+            10.into(),
+            vec![0; 32].into(),
+            (PERIOD * 10).into(),
+        );
+
+        assert_eq!(gc_inactive(&mut contract, PERIOD), 0);
+    }
+
+    #[test]
+    fn test_gc_keeps_user_with_standing_order() {
+        let mut contract = setup_contract();
+        testing_env!(get_context(accounts(4))
+            .storage_usage(env::storage_usage())
+            .attached_deposit(KEEPER_TIP + env::storage_byte_cost() * 1_000)
+            .build());
+        contract.create_standing_order(
+            "funded".to_owned(),
+            "empty".to_owned(),
+            10.into(),
+            PERIOD.into(),
+            1,
+            Some((PERIOD * 10).into()),
+        );
+
+        // Receiver of the order is kept
+        assert_eq!(gc_inactive(&mut contract, PERIOD), 0);
+        assert!(contract.get_accounts(accounts(3), None, None).is_some());
+    }
+}
@@ -0,0 +1,526 @@
+use crate::storage::assert_at_least_one_yocto;
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+// Delay in nanoseconds before a recovery can be finished (3 days)
+pub const RECOVERY_DELAY: u64 = 3 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Recovery {
+    // Account ID that becomes the owner of the account
+    pub new_owner_id: AccountId,
+
+    // Timestamp in nanoseconds from which the recovery can be finished
+    pub executable_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Guardian {
+    // Account ID allowed to freeze and recover the account
+    pub guardian_id: AccountId,
+
+    // Frozen account rejects outflows but accepts deposits
+    pub frozen: bool,
+
+    // Pending recovery started by the guardian, paid for by the guardian
+    pub recovery: Option<Recovery>,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Appoint a guardian for an account, replacing the current one
+    #[payable]
+    pub fn set_guardian(&mut self, account_name: String, guardian_id: AccountId) {
+        assert_at_least_one_yocto();
+        self.internal_assert_guardian_owner(&account_name);
+        self.internal_set_guardian(&account_name, guardian_id, env::attached_deposit() - 1);
+    }
+
+    // Remove the guardian of an account
+    #[payable]
+    pub fn remove_guardian(&mut self, account_name: String) {
+        assert_one_yocto();
+        self.internal_assert_guardian_owner(&account_name);
+        self.internal_remove_guardian(&account_name);
+    }
+
+    // Block outflows from an account
+    pub fn freeze_account(&mut self, account_name: String) {
+        let mut guardian = self.internal_get_guardian(&account_name);
+        guardian.frozen = true;
+        self.guardians.insert(&account_name, &guardian);
+    }
+
+    // Allow outflows from a frozen account again
+    pub fn unfreeze_account(&mut self, account_name: String) {
+        let mut guardian = self.internal_get_guardian(&account_name);
+        guardian.frozen = false;
+        self.guardians.insert(&account_name, &guardian);
+    }
+
+    // Start reassigning an account to a new owner after RECOVERY_DELAY
+    #[payable]
+    pub fn start_recovery(&mut self, account_name: String, new_owner_id: AccountId) {
+        let mut guardian = self.internal_get_guardian(&account_name);
+        require!(guardian.recovery.is_none(), "Recovery already started");
+        let account = self.accounts.get(&account_name).unwrap();
+
+        // Signers of a multi-signature account cannot be replaced by one recovery key
+        require!(
+            account.multisig.is_none(),
+            "Multi-signature account cannot be recovered"
+        );
+        require!(
+            account.owner_id != new_owner_id,
+            "New owner is the current owner"
+        );
+
+        // Guardian pays for the recovery storage
+        let initial_storage_usage = env::storage_usage();
+        guardian.recovery = Some(Recovery {
+            new_owner_id,
+            executable_at: (env::block_timestamp() + RECOVERY_DELAY).into(),
+        });
+        self.guardians.insert(&account_name, &guardian);
+        self.internal_charge_storage_with_deposit(
+            &guardian.guardian_id,
+            initial_storage_usage,
+            env::attached_deposit(),
+        );
+    }
+
+    // Cancel a pending recovery of an account owned by the caller
+    #[payable]
+    pub fn cancel_recovery(&mut self, account_name: String) {
+        assert_one_yocto();
+        let account = self
+            .accounts
+            .get(&account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"));
+        require!(
+            account.owner_id == env::signer_account_id(),
+            "Unauthorized access to account"
+        );
+        require!(
+            account.multisig.is_none(),
+            "Multi-signature account requires a proposal"
+        );
+
+        let mut guardian = self
+            .guardians
+            .get(&account_name)
+            .unwrap_or_else(|| panic!("Account has no guardian"));
+        require!(guardian.recovery.is_some(), "No recovery to cancel");
+        self.internal_clear_recovery(&account_name, &mut guardian);
+    }
+
+    // Finish a recovery after its delay, called by the new owner
+    #[payable]
+    pub fn finish_recovery(&mut self, account_name: String) {
+        let mut guardian = self
+            .guardians
+            .get(&account_name)
+            .unwrap_or_else(|| panic!("Account has no guardian"));
+        let recovery = guardian
+            .recovery
+            .clone()
+            .unwrap_or_else(|| panic!("No recovery to finish"));
+        require!(
+            recovery.new_owner_id == env::signer_account_id(),
+            "Unauthorized access to recovery"
+        );
+        require!(
+            env::block_timestamp() >= recovery.executable_at.0,
+            "Recovery delay has not passed"
+        );
+
+        // Recovered account is unfrozen for the new owner, without the access
+        // the previous owner granted
+        guardian.frozen = false;
+        self.internal_clear_recovery(&account_name, &mut guardian);
+        self.internal_revoke_account_access(&account_name);
+        self.internal_transfer_account(
+            &account_name,
+            &recovery.new_owner_id,
+            env::attached_deposit(),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get guardian of an account
+    pub fn get_guardian(&self, account_name: String) -> Option<Guardian> {
+        self.guardians.get(&account_name)
+    }
+}
+
+impl Contract {
+    // Whether a guardian has frozen the account
+    pub fn internal_is_frozen(&self, account_name: &String) -> bool {
+        self.guardians
+            .get(account_name)
+            .is_some_and(|guardian| guardian.frozen)
+    }
+
+    // Owner cannot grant access to an account a guardian has frozen or is recovering
+    pub fn internal_assert_not_guarded(&self, account_name: &String) {
+        if let Some(guardian) = self.guardians.get(account_name) {
+            require!(!guardian.frozen, "Account is frozen");
+            require!(guardian.recovery.is_none(), "Account is under recovery");
+        }
+    }
+
+    // Check that the caller alone controls the account
+    fn internal_assert_guardian_owner(&self, account_name: &String) {
+        let account = self
+            .accounts
+            .get(account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"));
+        require!(
+            account.owner_id == env::signer_account_id(),
+            "Unauthorized access to account"
+        );
+        require!(
+            account.multisig.is_none(),
+            "Multi-signature account requires a proposal"
+        );
+    }
+
+    // Appoint a guardian paid for by the account owner, unless a guardian action is pending
+    pub fn internal_set_guardian(
+        &mut self,
+        account_name: &String,
+        guardian_id: AccountId,
+        deposit: Balance,
+    ) {
+        self.internal_assert_guardian_idle(account_name);
+        let owner_id = self.accounts.get(account_name).unwrap().owner_id;
+        require!(guardian_id != owner_id, "Cannot appoint the account owner");
+
+        // Owner pays for the guardian storage
+        let initial_storage_usage = env::storage_usage();
+        self.guardians.insert(
+            account_name,
+            &Guardian {
+                guardian_id,
+                frozen: false,
+                recovery: None,
+            },
+        );
+        self.internal_charge_storage_with_deposit(&owner_id, initial_storage_usage, deposit);
+    }
+
+    // Remove the guardian of an account, unless a guardian action is pending
+    pub fn internal_remove_guardian(&mut self, account_name: &String) {
+        self.internal_assert_guardian_idle(account_name);
+        let owner_id = self.accounts.get(account_name).unwrap().owner_id;
+
+        let initial_storage_usage = env::storage_usage();
+        self.guardians.remove(account_name);
+        self.internal_refund_storage(&owner_id, initial_storage_usage);
+    }
+
+    fn internal_assert_guardian_idle(&self, account_name: &String) {
+        if let Some(guardian) = self.guardians.get(account_name) {
+            require!(
+                !guardian.frozen && guardian.recovery.is_none(),
+                "Guardian cannot be changed during freeze or recovery"
+            );
+        }
+    }
+
+    // Get guardian of an account appointed to the caller
+    fn internal_get_guardian(&self, account_name: &String) -> Guardian {
+        let guardian = self
+            .guardians
+            .get(account_name)
+            .unwrap_or_else(|| panic!("Account has no guardian"));
+        require!(
+            guardian.guardian_id == env::signer_account_id(),
+            "Unauthorized access to guardian"
+        );
+        guardian
+    }
+
+    // Remove allowances of an account, refunding their storage to the owner
+    fn internal_revoke_account_access(&mut self, account_name: &String) {
+        let account = self.accounts.get(account_name).unwrap();
+        let initial_storage_usage = env::storage_usage();
+        self.allowances.remove(account_name);
+        self.internal_refund_storage(&account.owner_id, initial_storage_usage);
+    }
+
+    // Remove pending recovery and refund its storage to the guardian
+    fn internal_clear_recovery(&mut self, account_name: &String, guardian: &mut Guardian) {
+        let initial_storage_usage = env::storage_usage();
+        guardian.recovery = None;
+        self.guardians.insert(account_name, guardian);
+        self.internal_refund_storage(&guardian.guardian_id, initial_storage_usage);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::multisig::ProposalKind;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        setup_user(&mut contract, &accounts(1), &["payroll", "savings"]);
+        // accounts(3) is the guardian and accounts(4) the recovery key
+        setup_user(&mut contract, &accounts(3), &[]);
+        setup_user(&mut contract, &accounts(4), &[]);
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.internal_deposit("payroll".to_owned(), 100.into());
+        contract.set_guardian("payroll".to_owned(), accounts(3));
+        contract
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is frozen")]
+    fn test_freeze_account() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.freeze_account("payroll".to_owned());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw("payroll".to_owned(), 1.into());
+    }
+
+    #[test]
+    fn test_deposit_into_frozen_account() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.freeze_account("payroll".to_owned());
+        contract.internal_deposit("payroll".to_owned(), 10.into());
+        assert_eq!(
+            contract.get_balance("payroll".to_owned()).unwrap(),
+            110.into()
+        );
+        assert_eq!(
+            contract
+                .get_transfer_quote("payroll".to_owned(), "savings".to_owned(), 1.into())
+                .error,
+            Some("Account is frozen".to_owned())
+        );
+
+        contract.unfreeze_account("payroll".to_owned());
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.transfer("payroll".to_owned(), "savings".to_owned(), 10.into());
+    }
+
+    #[test]
+    fn test_finish_recovery() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.freeze_account("payroll".to_owned());
+        contract.start_recovery("payroll".to_owned(), accounts(4));
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context.block_timestamp(RECOVERY_DELAY).build());
+        contract.finish_recovery("payroll".to_owned());
+        assert_eq!(
+            contract.get_account("payroll".to_owned()).unwrap().owner_id,
+            accounts(4)
+        );
+        assert_eq!(
+            contract.get_accounts(accounts(1), None, None),
+            Some(vec!["savings".to_owned()])
+        );
+
+        // New owner can withdraw from the unfrozen account
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw("payroll".to_owned(), 100.into());
+    }
+
+    #[test]
+    fn test_finish_recovery_revokes_access() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.approve("payroll".to_owned(), accounts(5), 50.into(), None);
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.start_recovery("payroll".to_owned(), accounts(4));
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context.block_timestamp(RECOVERY_DELAY).build());
+        contract.finish_recovery("payroll".to_owned());
+        assert!(contract.get_allowances("payroll".to_owned()).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Multi-signature account requires a proposal")]
+    fn test_set_guardian_multisig_single_signer() {
+        let mut contract = setup_contract();
+
+        // One leaked key of a 2-of-3 account cannot appoint its own guardian
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.set_multisig(
+            "savings".to_owned(),
+            vec![accounts(1), accounts(4), accounts(5)],
+            2,
+            1_000.into(),
+        );
+        contract.set_guardian("savings".to_owned(), accounts(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Multi-signature account cannot be recovered")]
+    fn test_start_recovery_multisig() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.set_multisig(
+            "payroll".to_owned(),
+            vec![accounts(1), accounts(4), accounts(5)],
+            2,
+            1_000.into(),
+        );
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.start_recovery("payroll".to_owned(), accounts(4));
+    }
+
+    #[test]
+    fn test_set_guardian_by_proposal() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.set_multisig(
+            "savings".to_owned(),
+            vec![accounts(1), accounts(4), accounts(5)],
+            2,
+            1_000.into(),
+        );
+        contract.create_proposal(
+            "savings".to_owned(),
+            ProposalKind::SetGuardian {
+                guardian_id: accounts(3),
+            },
+        );
+        assert!(contract.get_guardian("savings".to_owned()).is_none());
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.confirm_proposal(0.into());
+        assert_eq!(
+            contract
+                .get_guardian("savings".to_owned())
+                .unwrap()
+                .guardian_id,
+            accounts(3)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is frozen")]
+    fn test_approve_frozen_account() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.freeze_account("payroll".to_owned());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.approve("payroll".to_owned(), accounts(5), 50.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is under recovery")]
+    fn test_set_multisig_during_recovery() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.start_recovery("payroll".to_owned(), accounts(4));
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_multisig(
+            "payroll".to_owned(),
+            vec![accounts(1), accounts(5)],
+            1,
+            1_000.into(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Recovery delay has not passed")]
+    fn test_finish_recovery_before_delay() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.start_recovery("payroll".to_owned(), accounts(4));
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context.block_timestamp(RECOVERY_DELAY - 1).build());
+        contract.finish_recovery("payroll".to_owned());
+    }
+
+    #[test]
+    #[should_panic(expected = "No recovery to finish")]
+    fn test_cancel_recovery() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.start_recovery("payroll".to_owned(), accounts(4));
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.cancel_recovery("payroll".to_owned());
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context.block_timestamp(RECOVERY_DELAY).build());
+        contract.finish_recovery("payroll".to_owned());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access to guardian")]
+    fn test_freeze_account_unauthorized_access() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.freeze_account("payroll".to_owned());
+    }
+}
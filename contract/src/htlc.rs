@@ -0,0 +1,269 @@
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, require, AccountId};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Htlc {
+    pub id: U64,
+
+    // Account the tokens were locked from
+    pub sender_account_name: String,
+
+    // Owner of the sender account who paid for the HTLC storage
+    pub sender_id: AccountId,
+
+    // Account the tokens are paid to when the preimage is revealed
+    pub receiver_account_name: String,
+
+    pub amount: U128,
+
+    // SHA-256 hash of the preimage
+    pub hash: Base64VecU8,
+
+    // Timestamp in nanoseconds from which the tokens can only be refunded
+    pub timeout: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Lock tokens from sender account until the preimage of hash is revealed
+    #[payable]
+    pub fn lock_htlc(
+        &mut self,
+        sender_account_name: String,
+        receiver_account_name: String,
+        amount: U128,
+        sha256_hash: Base64VecU8,
+        timeout: U64,
+    ) -> U64 {
+        require!(!self.paused.transfers, "Transfers are paused");
+        require!(amount.0 > 0, "HTLC amount must be positive");
+        require!(sha256_hash.0.len() == 32, "Hash must be 32 bytes");
+        require!(
+            timeout.0 > env::block_timestamp(),
+            "Timeout must be in the future"
+        );
+        require!(
+            self.accounts.get(&receiver_account_name).is_some(),
+            "Receiver account does not exist"
+        );
+        require!(
+            sender_account_name != receiver_account_name,
+            "Cannot transfer to the same account"
+        );
+
+        // Hold the amount until the HTLC is claimed or refunded
+        self.internal_withdraw_from_account(&sender_account_name, amount.into());
+
+        // Sender pays for the HTLC storage
+        let initial_storage_usage = env::storage_usage();
+        let id = self.next_htlc_id;
+        self.next_htlc_id += 1;
+        let htlc = Htlc {
+            id: id.into(),
+            sender_account_name,
+            sender_id: env::signer_account_id(),
+            receiver_account_name,
+            amount,
+            hash: sha256_hash,
+            timeout,
+        };
+        self.htlcs.insert(&id, &htlc);
+        self.internal_add_open_transfer(&htlc.sender_account_name, &htlc.receiver_account_name);
+        self.internal_charge_storage_with_deposit(
+            &htlc.sender_id,
+            initial_storage_usage,
+            env::attached_deposit(),
+        );
+        id.into()
+    }
+
+    // Pay locked tokens to the receiver account by revealing the preimage. Claims
+    // are not paused, since a pause covering the timeout would let the sender
+    // refund after the counterparty already paid on the other chain.
+    pub fn claim_htlc(&mut self, htlc_id: U64, preimage: Base64VecU8) {
+        let htlc = self
+            .htlcs
+            .get(&htlc_id.0)
+            .unwrap_or_else(|| panic!("HTLC does not exist"));
+        require!(
+            env::block_timestamp() < htlc.timeout.0,
+            "HTLC has timed out"
+        );
+        require!(env::sha256(&preimage.0) == htlc.hash.0, "Invalid preimage");
+
+        // Receiver gets the amount minus the transfer fee, as with transfer
+        let mut receiver_account = self
+            .accounts
+            .get(&htlc.receiver_account_name)
+            .unwrap_or_else(|| panic!("Receiver account does not exist"));
+        let transfer_fee = self
+            .internal_credit_with_fee(&htlc.sender_id, &mut receiver_account, htlc.amount.0)
+            .unwrap_or_else(|err| panic!("{}", err));
+        self.internal_collect_transfer_fee(&htlc.sender_id, transfer_fee);
+        self.accounts
+            .insert(&htlc.receiver_account_name, &receiver_account);
+
+        // Preimage is logged for the counterparty on the other chain
+        log!(
+            "HTLC {} claimed with preimage {}",
+            htlc_id.0,
+            near_sdk::serde_json::to_string(&preimage).unwrap()
+        );
+        self.internal_remove_htlc(&htlc);
+    }
+
+    // Return locked tokens to the sender account after the timeout
+    pub fn refund_htlc(&mut self, htlc_id: U64) {
+        require!(!self.paused.transfers, "Transfers are paused");
+        let htlc = self
+            .htlcs
+            .get(&htlc_id.0)
+            .unwrap_or_else(|| panic!("HTLC does not exist"));
+        require!(
+            env::block_timestamp() >= htlc.timeout.0,
+            "HTLC has not timed out"
+        );
+
+        self.internal_refund(&htlc.sender_account_name, htlc.amount.0);
+        self.internal_remove_htlc(&htlc);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get HTLC by ID
+    pub fn get_htlc(&self, htlc_id: U64) -> Option<Htlc> {
+        self.htlcs.get(&htlc_id.0)
+    }
+}
+
+impl Contract {
+    // Remove HTLC and refund its storage to the sender
+    fn internal_remove_htlc(&mut self, htlc: &Htlc) {
+        let initial_storage_usage = env::storage_usage();
+        self.htlcs.remove(&htlc.id.0);
+        self.internal_remove_open_transfer(&htlc.sender_account_name, &htlc.receiver_account_name);
+        self.internal_refund_storage(&htlc.sender_id, initial_storage_usage);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    const TIMEOUT: u64 = 1_000;
+    const PREIMAGE: &[u8] = b"secret";
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        for account_id in [accounts(1), accounts(3)] {
+            setup_user(&mut contract, &account_id, &[account_id.as_str()]);
+        }
+        contract.internal_deposit(accounts(1).to_string(), 100.into());
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.lock_htlc(
+            accounts(1).to_string(),
+            accounts(3).to_string(),
+            100.into(),
+            env::sha256(PREIMAGE).into(),
+            TIMEOUT.into(),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_claim_htlc() {
+        let mut contract = setup_contract();
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            0.into()
+        );
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.block_timestamp(TIMEOUT - 1).build());
+        contract.claim_htlc(0.into(), PREIMAGE.to_vec().into());
+        assert_eq!(
+            contract.get_balance(accounts(3).to_string()).unwrap(),
+            99.into()
+        );
+        assert!(contract.get_htlc(0.into()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid preimage")]
+    fn test_claim_htlc_invalid_preimage() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.claim_htlc(0.into(), b"guess".to_vec().into());
+    }
+
+    #[test]
+    #[should_panic(expected = "HTLC has timed out")]
+    fn test_claim_htlc_at_timeout() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.block_timestamp(TIMEOUT).build());
+        contract.claim_htlc(0.into(), PREIMAGE.to_vec().into());
+    }
+
+    #[test]
+    fn test_refund_htlc() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(TIMEOUT).build());
+        contract.refund_htlc(0.into());
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            100.into()
+        );
+        assert!(contract.get_htlc(0.into()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "HTLC has not timed out")]
+    fn test_refund_htlc_before_timeout() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(TIMEOUT - 1).build());
+        contract.refund_htlc(0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "HTLC does not exist")]
+    fn test_refund_htlc_after_claim() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.block_timestamp(TIMEOUT - 1).build());
+        contract.claim_htlc(0.into(), PREIMAGE.to_vec().into());
+
+        testing_env!(context.block_timestamp(TIMEOUT).build());
+        contract.refund_htlc(0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "HTLC does not exist")]
+    fn test_claim_htlc_after_refund() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(TIMEOUT).build());
+        contract.refund_htlc(0.into());
+
+        testing_env!(context.block_timestamp(TIMEOUT - 1).build());
+        contract.claim_htlc(0.into(), PREIMAGE.to_vec().into());
+    }
+}
@@ -1,20 +1,62 @@
+use crate::account::AccountMap;
+use crate::allowance::Allowance;
+use crate::escrow::Escrow;
+use crate::gc::DEFAULT_INACTIVITY_PERIOD;
+use crate::guardian::Guardian;
+use crate::htlc::Htlc;
+use crate::limit::SpendingLimit;
+use crate::multisig::{Multisig, Proposal};
+use crate::pause::PauseFlags;
+use crate::referral::ReferralStats;
+use crate::role::Role;
+use crate::sponsor::Sponsorship;
+use crate::standing_order::StandingOrder;
+use crate::subsidy::StorageSubsidy;
+use crate::upgrade::write_state_version;
+use crate::user::UserAccounts;
+use crate::vesting::Vesting;
 use near_contract_standards::fungible_token::core::ext_ft_core;
-use near_contract_standards::storage_management::{StorageBalance, StorageManagement};
+use near_contract_standards::storage_management::StorageBalance;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LookupMap;
+use near_sdk::collections::{LookupMap, UnorderedMap};
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
     assert_one_yocto, env, near_bindgen, require, AccountId, Balance, PanicOnDefault, Promise,
 };
 
+pub mod account;
+pub mod allowance;
+pub mod escrow;
+pub mod event;
+pub mod gc;
+pub mod guardian;
+pub mod htlc;
+pub mod limit;
+pub mod lock;
 pub mod msg;
+pub mod multisig;
+pub mod ownership;
+pub mod pause;
 pub mod receiver;
+pub mod referral;
+pub mod role;
+pub mod sponsor;
+pub mod standing_order;
 pub mod storage;
+pub mod storage_cost;
+pub mod subsidy;
 mod test;
+pub mod upgrade;
+pub mod user;
+pub mod vesting;
+pub mod withdraw;
 
 const ACCOUNT_NAME_MAX_LENGTH: usize = 256;
 
+// Maximum number of legs in a batch transfer
+const MAX_BATCH_SIZE: usize = 100;
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
@@ -25,13 +67,109 @@ pub struct Contract {
     pub total_transfer_fee: Balance,
 
     // Account account_name -> Account
-    pub accounts: LookupMap<String, Account>,
+    pub accounts: AccountMap,
 
     // User's Account ID -> List of account names
-    pub user_accounts: LookupMap<AccountId, Vec<String>>,
+    pub user_accounts: UserAccounts,
 
     // Storage staking balance
     pub storage_balances: LookupMap<AccountId, StorageBalance>,
+
+    // Account account_name -> Number of withdrawals waiting for the token transfer result
+    pub pending_withdrawals: LookupMap<String, u32>,
+
+    // Referred user's Account ID -> Referrer's Account ID
+    pub referrers: LookupMap<AccountId, AccountId>,
+
+    // Referrer's Account ID -> Referral stats
+    pub referral_stats: LookupMap<AccountId, ReferralStats>,
+
+    // Proposal ID -> Multi-signature proposal
+    pub proposals: LookupMap<u64, Proposal>,
+
+    // Account account_name -> List of proposal IDs
+    pub account_proposals: LookupMap<String, Vec<u64>>,
+
+    // ID of the next multi-signature proposal
+    pub next_proposal_id: u64,
+
+    // Account account_name -> List of spending allowances
+    pub allowances: LookupMap<String, Vec<Allowance>>,
+
+    // Account account_name -> Outflow limits and history
+    pub spending_limits: LookupMap<String, SpendingLimit>,
+
+    // Vesting ID -> Linear vesting stream
+    pub vestings: LookupMap<u64, Vesting>,
+
+    // ID of the next vesting stream
+    pub next_vesting_id: u64,
+
+    // Standing order ID -> Recurring transfer
+    pub standing_orders: UnorderedMap<u64, StandingOrder>,
+
+    // ID of the next standing order
+    pub next_standing_order_id: u64,
+
+    // Index in standing_orders where the next execution of due orders starts
+    pub standing_order_cursor: u64,
+
+    // Escrow ID -> Escrow between two accounts
+    pub escrows: LookupMap<u64, Escrow>,
+
+    // Account name -> IDs of escrows the account sends or receives
+    pub account_escrows: LookupMap<String, Vec<u64>>,
+
+    // ID of the next escrow
+    pub next_escrow_id: u64,
+
+    // Account name -> Number of vestings, HTLCs and standing orders the account
+    // sends or receives
+    pub account_open_transfers: LookupMap<String, u32>,
+
+    // HTLC ID -> Hash-time-locked transfer
+    pub htlcs: LookupMap<u64, Htlc>,
+
+    // ID of the next HTLC
+    pub next_htlc_id: u64,
+
+    // Account name -> Guardian appointed by the account owner
+    pub guardians: LookupMap<String, Guardian>,
+
+    // Operations paused contract-wide
+    pub paused: PauseFlags,
+
+    // Account ID allowed to pause but not unpause
+    pub pause_guardian: Option<AccountId>,
+
+    // Account ID -> Roles granted to the account
+    pub roles: LookupMap<AccountId, Vec<Role>>,
+
+    // Account ID proposed as the next contract owner
+    pub proposed_owner_id: Option<AccountId>,
+
+    // Pool of NEAR paying the storage of users registered with tokens
+    pub storage_subsidy: StorageSubsidy,
+
+    // User's Account ID -> Storage balance paid from the subsidy pool
+    pub storage_subsidies: LookupMap<AccountId, Balance>,
+
+    // User's Account ID -> Sponsor who deposited storage balance for the user
+    pub sponsorships: LookupMap<AccountId, Sponsorship>,
+
+    // User's Account ID -> Timestamp in nanoseconds of the user's last storage
+    // or account creation activity
+    pub user_activity: UnorderedMap<AccountId, u64>,
+
+    // Timestamp in nanoseconds since which user activity is tracked, taken as the
+    // last activity of users registered before and not active since
+    pub activity_tracked_since: u64,
+
+    // Duration in nanoseconds without activity after which a user can be removed
+    pub inactivity_period: u64,
+
+    // Index in user_activity where the next removal of inactive users starts
+    pub gc_cursor: u64,
 }
 
 #[near_bindgen]
@@ -45,87 +183,57 @@ impl Contract {
         transfer_fee_denominator: U128,
     ) -> Self {
         require!(!env::state_exists(), "Already initialized");
-        let mut this = Self {
-            metadata: ContractMetadata {
-                owner_id,
-                token_id,
-                transfer_fee_numerator,
-                transfer_fee_denominator,
-                user_storage_usage: 0.into(),
-                account_storage_usage: 0.into(),
-            },
-            total_transfer_fee: 0,
-            accounts: LookupMap::new(b"a".to_vec()),
-            user_accounts: LookupMap::new(b"u".to_vec()),
-            storage_balances: LookupMap::new(b"s".to_vec()),
-        };
+        let mut this = Self::internal_new(ContractMetadata {
+            owner_id,
+            token_id,
+            transfer_fee_numerator,
+            transfer_fee_denominator,
+            referral_fee_numerator: 0.into(),
+            referral_fee_denominator: 1.into(),
+            user_storage_usage: 0.into(),
+            account_storage_usage: 0.into(),
+        });
+        write_state_version();
         this.measure_account_storage_usage();
         this
     }
 
-    // Create new account with unique account name
+    // Create new account with unique account name and optional referrer
     #[payable]
-    pub fn create_account(&mut self, account_name: String) {
+    pub fn create_account(&mut self, account_name: String, referrer_id: Option<AccountId>) {
+        require!(!self.paused.account_creation, "Account creation is paused");
         require!(
             self.user_accounts.contains_key(&env::signer_account_id()),
             format!("The user {} is not registered", env::signer_account_id())
         );
 
-        // Account name must not be longer than ACCOUNT_NAME_MAX_LENGTH
-        require!(
-            account_name.len() <= ACCOUNT_NAME_MAX_LENGTH,
-            "Account name too long"
-        );
+        self.internal_assert_new_account_name(&account_name);
 
-        // Account name must be unique
-        require!(
-            !self.accounts.contains_key(&account_name),
-            "Account already exists"
-        );
-
-        // User may attach deposit to create new account
+        // User may attach deposit to create new account, the excess is refunded
+        let deposit = env::attached_deposit();
         self.storage_deposit(Some(env::signer_account_id()), None);
 
+        // Record referrer of the user
+        if let Some(referrer_id) = referrer_id {
+            self.internal_set_referrer(&env::signer_account_id(), referrer_id, 0);
+        }
+
         // Create new account
         self.internal_create_account(env::signer_account_id(), account_name);
+        self.internal_refund_unused_deposit(&env::signer_account_id(), deposit);
     }
 
     // Withdraw tokens from account
     #[payable]
     pub fn withdraw(&mut self, account_name: String, amount: U128) -> Option<Promise> {
         assert_one_yocto();
-        require!(
-            self.user_accounts.contains_key(&env::signer_account_id()),
-            format!("The user {} is not registered", env::signer_account_id())
-        );
-
-        // Get account by account name
-        let mut account = self
-            .accounts
-            .get(&account_name)
-            .unwrap_or_else(|| panic!("Account does not exist"));
-
-        // Check if account owner is the same as the caller
-        require!(
-            account.owner_id == env::signer_account_id(),
-            "Unauthorized access to account"
-        );
-
-        // Subtract amount from account balance
-        account.balance = account
-            .balance
-            .checked_sub(amount.into())
-            .unwrap_or_else(|| panic!("Balance overflow"));
-        self.accounts.insert(&account_name, &account);
+        require!(!self.paused.withdrawals, "Withdrawals are paused");
+        self.internal_withdraw_from_account(&account_name, amount.into());
 
         // Contract owner cannot withdraw tokens from itself
         if env::current_account_id() != env::signer_account_id() {
             // Call token contract to transfer token to caller
-            Some(
-                ext_ft_core::ext(self.metadata.token_id.clone())
-                    .with_attached_deposit(1)
-                    .ft_transfer(env::signer_account_id(), amount, None),
-            )
+            Some(self.internal_ft_transfer(account_name, env::signer_account_id(), amount, None))
         } else {
             None
         }
@@ -143,6 +251,37 @@ impl Contract {
             format!("The user {} is not registered", env::signer_account_id())
         );
 
+        let (sender_account, receiver_account, transfer_fee) = self
+            .internal_prepare_transfer(
+                &sender_account_name,
+                &receiver_account_name,
+                amount.into(),
+                Some(&env::signer_account_id()),
+            )
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        self.internal_collect_transfer_fee(&env::signer_account_id(), transfer_fee);
+        self.internal_before_outflow(&sender_account_name, &sender_account, amount.into());
+
+        // Update accounts in storage
+        self.accounts.insert(&sender_account_name, &sender_account);
+        self.accounts
+            .insert(&receiver_account_name, &receiver_account);
+    }
+
+    // Transfer tokens from one account to many accounts atomically
+    pub fn batch_transfer(
+        &mut self,
+        sender_account_name: String,
+        transfers: Vec<(String, U128)>,
+    ) -> Vec<TransferResult> {
+        require!(
+            self.user_accounts.contains_key(&env::signer_account_id()),
+            format!("The user {} is not registered", env::signer_account_id())
+        );
+        require!(!transfers.is_empty(), "No transfers to execute");
+        require!(transfers.len() <= MAX_BATCH_SIZE, "Too many transfers");
+
         // Get sender account by account name
         let mut sender_account = self
             .accounts
@@ -152,55 +291,57 @@ impl Contract {
             sender_account.owner_id == env::signer_account_id(),
             "Unauthorized access to account"
         );
+        require!(
+            sender_account.multisig.is_none(),
+            "Multi-signature account requires a proposal"
+        );
 
-        // Get receiver account by account name
-        let mut receiver_account = self
-            .accounts
-            .get(&receiver_account_name)
-            .unwrap_or_else(|| panic!("Receiver account does not exist"));
-
-        // Transfer tokens from sender to receiver
-        sender_account.balance = sender_account
-            .balance
-            .checked_sub(amount.into())
-            .unwrap_or_else(|| panic!("Balance overflow"));
-        receiver_account.balance = receiver_account
-            .balance
-            .checked_add(amount.into())
-            .unwrap_or_else(|| panic!("Balance overflow"));
-
-        // If accounts have different owners, subtract transfer fee from receiver
-        if receiver_account.owner_id != env::signer_account_id() {
-            let transfer_fee = Balance::from(amount)
-                .checked_mul(self.metadata.transfer_fee_numerator.into())
-                .unwrap_or(0)
-                .checked_div(self.metadata.transfer_fee_denominator.into())
-                .unwrap_or(0);
-            receiver_account.balance = receiver_account
-                .balance
-                .checked_sub(transfer_fee)
-                .unwrap_or_else(|| panic!("Balance overflow"));
-
-            self.total_transfer_fee = self
-                .total_transfer_fee
+        let sender_account_balance = sender_account.balance;
+        let mut total_transfer_fee: Balance = 0;
+        let mut results = Vec::with_capacity(transfers.len());
+        for (receiver_account_name, amount) in transfers {
+            require!(
+                receiver_account_name != sender_account_name,
+                "Cannot transfer to the same account"
+            );
+
+            // Receiver is written on every leg so repeated receivers stay consistent
+            let mut receiver_account = self
+                .accounts
+                .get(&receiver_account_name)
+                .unwrap_or_else(|| panic!("Receiver account does not exist"));
+            let transfer_fee = self
+                .internal_apply_transfer(&mut sender_account, &mut receiver_account, amount.into())
+                .unwrap_or_else(|err| panic!("{}", err));
+            self.accounts
+                .insert(&receiver_account_name, &receiver_account);
+
+            total_transfer_fee = total_transfer_fee
                 .checked_add(transfer_fee)
                 .unwrap_or_else(|| panic!("Balance overflow"));
+            results.push(TransferResult {
+                receiver_account_name,
+                transfer_fee: transfer_fee.into(),
+                received_amount: amount.0.saturating_sub(transfer_fee).into(),
+            });
         }
+        self.internal_collect_transfer_fee(&env::signer_account_id(), total_transfer_fee);
+        self.internal_before_outflow(
+            &sender_account_name,
+            &sender_account,
+            sender_account_balance - sender_account.balance,
+        );
 
-        // Update accounts in storage
+        // Update sender account in storage once
         self.accounts.insert(&sender_account_name, &sender_account);
-        self.accounts
-            .insert(&receiver_account_name, &receiver_account);
+        results
     }
 
-    // Withdraw all fees to contract owner
+    // Withdraw fees to contract owner, called by a fee manager
     #[payable]
     pub fn withdraw_transfer_fee(&mut self, amount: U128) -> Option<Promise> {
         assert_one_yocto();
-        require!(
-            env::signer_account_id() == self.metadata.owner_id,
-            "Unauthorized access"
-        );
+        self.internal_assert_role(Role::FeeManager);
         require!(
             env::current_account_id() != self.metadata.owner_id,
             "Contract cannot withdraw from itself"
@@ -232,9 +373,27 @@ impl Contract {
         self.metadata.clone()
     }
 
-    // Get list of all accounts owned by a user
-    pub fn get_accounts(&self, account_id: AccountId) -> Option<Vec<String>> {
-        self.user_accounts.get(&account_id)
+    // Get list of accounts owned by a user, paged by from_index and limit
+    pub fn get_accounts(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U64>,
+        limit: Option<u64>,
+    ) -> Option<Vec<String>> {
+        self.user_accounts.get(
+            &account_id,
+            from_index.map_or(0, |index| index.0),
+            limit.unwrap_or(u64::MAX),
+        )
+    }
+
+    // Get account by account name
+    pub fn get_account(&self, account_name: String) -> Option<AccountView> {
+        self.accounts.get(&account_name).map(|account| AccountView {
+            owner_id: account.owner_id,
+            balance: account.balance.into(),
+            unlock_at: account.unlock_at.map(U64),
+        })
     }
 
     // Get balance of an account
@@ -244,16 +403,261 @@ impl Contract {
             .get(&account_name)
             .map(|account| account.balance.into())
     }
+
+    // Preview the transfer fee and resulting balances of a transfer
+    pub fn get_transfer_quote(
+        &self,
+        sender_account_name: String,
+        receiver_account_name: String,
+        amount: U128,
+    ) -> TransferQuote {
+        let result = self
+            .internal_prepare_transfer(
+                &sender_account_name,
+                &receiver_account_name,
+                amount.into(),
+                None,
+            )
+            .and_then(|prepared| {
+                // Quote is for a direct transfer, which needs a proposal on multi-signature accounts
+                if prepared.0.multisig.is_some() {
+                    return Err("Multi-signature account requires a proposal");
+                }
+                self.internal_check_outflow(&sender_account_name, amount.into())?;
+                Ok(prepared)
+            });
+        match result {
+            Ok((sender_account, receiver_account, transfer_fee)) => TransferQuote {
+                transfer_fee: transfer_fee.into(),
+                received_amount: amount.0.saturating_sub(transfer_fee).into(),
+                is_cross_owner: sender_account.owner_id != receiver_account.owner_id,
+                sender_balance: sender_account.balance.into(),
+                receiver_balance: receiver_account.balance.into(),
+                error: None,
+            },
+            Err(err) => TransferQuote {
+                transfer_fee: 0.into(),
+                received_amount: 0.into(),
+                is_cross_owner: false,
+                sender_balance: 0.into(),
+                receiver_balance: 0.into(),
+                error: Some(err.to_owned()),
+            },
+        }
+    }
 }
 
 impl Contract {
+    // Create contract state with empty collections
+    fn internal_new(metadata: ContractMetadata) -> Self {
+        Self {
+            metadata,
+            total_transfer_fee: 0,
+            accounts: AccountMap::new(b"a".to_vec()),
+            user_accounts: UserAccounts::new(b"n".to_vec(), b"u".to_vec()),
+            storage_balances: LookupMap::new(b"s".to_vec()),
+            pending_withdrawals: LookupMap::new(b"d".to_vec()),
+            referrers: LookupMap::new(b"r".to_vec()),
+            referral_stats: LookupMap::new(b"w".to_vec()),
+            proposals: LookupMap::new(b"p".to_vec()),
+            account_proposals: LookupMap::new(b"q".to_vec()),
+            next_proposal_id: 0,
+            allowances: LookupMap::new(b"l".to_vec()),
+            spending_limits: LookupMap::new(b"m".to_vec()),
+            vestings: LookupMap::new(b"v".to_vec()),
+            next_vesting_id: 0,
+            standing_orders: UnorderedMap::new(b"o".to_vec()),
+            next_standing_order_id: 0,
+            standing_order_cursor: 0,
+            escrows: LookupMap::new(b"e".to_vec()),
+            account_escrows: LookupMap::new(b"f".to_vec()),
+            next_escrow_id: 0,
+            account_open_transfers: LookupMap::new(b"k".to_vec()),
+            htlcs: LookupMap::new(b"h".to_vec()),
+            next_htlc_id: 0,
+            guardians: LookupMap::new(b"g".to_vec()),
+            paused: PauseFlags::default(),
+            pause_guardian: None,
+            roles: LookupMap::new(b"x".to_vec()),
+            proposed_owner_id: None,
+            storage_subsidy: StorageSubsidy::default(),
+            storage_subsidies: LookupMap::new(b"y".to_vec()),
+            sponsorships: LookupMap::new(b"z".to_vec()),
+            user_activity: UnorderedMap::new(b"j".to_vec()),
+            activity_tracked_since: env::block_timestamp(),
+            inactivity_period: DEFAULT_INACTIVITY_PERIOD,
+            gc_cursor: 0,
+        }
+    }
+
+    pub fn internal_assert_new_account_name(&self, account_name: &String) {
+        // Account name must not be longer than ACCOUNT_NAME_MAX_LENGTH
+        require!(
+            account_name.len() <= ACCOUNT_NAME_MAX_LENGTH,
+            "Account name too long"
+        );
+
+        // Account name must be unique
+        require!(
+            !self.accounts.contains_key(account_name),
+            "Account already exists"
+        );
+    }
+
+    // Calculate transfer fee for cross-owner transfer
+    pub fn internal_transfer_fee(&self, amount: Balance) -> Balance {
+        amount
+            .checked_mul(self.metadata.transfer_fee_numerator.into())
+            .unwrap_or(0)
+            .checked_div(self.metadata.transfer_fee_denominator.into())
+            .unwrap_or(0)
+    }
+
+    // Compute sender and receiver accounts after a transfer along with the transfer fee.
+    // If owner_id is given, the sender account must be owned by owner_id.
+    pub fn internal_prepare_transfer(
+        &self,
+        sender_account_name: &String,
+        receiver_account_name: &String,
+        amount: Balance,
+        owner_id: Option<&AccountId>,
+    ) -> Result<(Account, Account, Balance), &'static str> {
+        // Get sender account by account name
+        let mut sender_account = self
+            .accounts
+            .get(sender_account_name)
+            .ok_or("Sender account does not exist")?;
+        if let Some(owner_id) = owner_id {
+            if &sender_account.owner_id != owner_id {
+                return Err("Unauthorized access to account");
+            }
+            if sender_account.multisig.is_some() {
+                return Err("Multi-signature account requires a proposal");
+            }
+        }
+        if sender_account.is_locked() {
+            return Err("Account is locked");
+        }
+        if self.internal_is_frozen(sender_account_name) {
+            return Err("Account is frozen");
+        }
+
+        // Get receiver account by account name
+        let mut receiver_account = self
+            .accounts
+            .get(receiver_account_name)
+            .ok_or("Receiver account does not exist")?;
+        if sender_account_name == receiver_account_name {
+            return Err("Cannot transfer to the same account");
+        }
+
+        let transfer_fee =
+            self.internal_apply_transfer(&mut sender_account, &mut receiver_account, amount)?;
+        Ok((sender_account, receiver_account, transfer_fee))
+    }
+
+    // Move amount from sender to receiver balance and return the transfer fee charged
+    pub fn internal_apply_transfer(
+        &self,
+        sender_account: &mut Account,
+        receiver_account: &mut Account,
+        amount: Balance,
+    ) -> Result<Balance, &'static str> {
+        // Transfer tokens from sender to receiver
+        sender_account.balance = sender_account
+            .balance
+            .checked_sub(amount)
+            .ok_or("Balance overflow")?;
+        self.internal_credit_transfer(&sender_account.owner_id, receiver_account, amount)
+    }
+
+    // Add amount sent by sender_id to receiver balance and return the transfer fee charged
+    pub fn internal_credit_transfer(
+        &self,
+        sender_id: &AccountId,
+        receiver_account: &mut Account,
+        amount: Balance,
+    ) -> Result<Balance, &'static str> {
+        if self.paused.transfers {
+            return Err("Transfers are paused");
+        }
+        self.internal_credit_with_fee(sender_id, receiver_account, amount)
+    }
+
+    // Same as internal_credit_transfer but also while transfers are paused
+    pub fn internal_credit_with_fee(
+        &self,
+        sender_id: &AccountId,
+        receiver_account: &mut Account,
+        amount: Balance,
+    ) -> Result<Balance, &'static str> {
+        receiver_account.balance = receiver_account
+            .balance
+            .checked_add(amount)
+            .ok_or("Balance overflow")?;
+
+        // If accounts have different owners, subtract transfer fee from receiver
+        let mut transfer_fee = 0;
+        if &receiver_account.owner_id != sender_id {
+            transfer_fee = self.internal_transfer_fee(amount);
+            receiver_account.balance = receiver_account
+                .balance
+                .checked_sub(transfer_fee)
+                .ok_or("Balance overflow")?;
+        }
+
+        Ok(transfer_fee)
+    }
+
+    // Check that tokens may leave the account and record the outflow
+    pub fn internal_before_outflow(
+        &mut self,
+        account_name: &String,
+        account: &Account,
+        amount: Balance,
+    ) {
+        self.internal_try_before_outflow(account_name, account, amount)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    // Same as internal_before_outflow but returns the error instead of panicking
+    pub fn internal_try_before_outflow(
+        &mut self,
+        account_name: &String,
+        account: &Account,
+        amount: Balance,
+    ) -> Result<(), &'static str> {
+        if account.is_locked() {
+            return Err("Account is locked");
+        }
+        if self.internal_is_frozen(account_name) {
+            return Err("Account is frozen");
+        }
+        self.internal_record_outflow(account_name, &account.owner_id, amount)
+    }
+
+    // Add transfer fee to the contract after rewarding the sender's referrer
+    pub fn internal_collect_transfer_fee(&mut self, sender_id: &AccountId, transfer_fee: Balance) {
+        if transfer_fee == 0 {
+            return;
+        }
+
+        // Share part of the transfer fee with the sender's referrer
+        let referral_reward = self.internal_reward_referrer(sender_id, transfer_fee);
+        self.total_transfer_fee = self
+            .total_transfer_fee
+            .checked_add(transfer_fee - referral_reward)
+            .unwrap_or_else(|| panic!("Balance overflow"));
+    }
+
     fn measure_account_storage_usage(&mut self) {
         let initial_storage_usage = env::storage_usage();
         let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
         let tmp_account_name = "a".repeat(ACCOUNT_NAME_MAX_LENGTH);
 
-        // Calculate storage usage for new user
-        self.user_accounts.insert(&tmp_account_id, &vec![]);
+        // Calculate storage usage for new user, who may have a sponsor
+        self.user_accounts.insert_user(&tmp_account_id);
+        self.user_activity.insert(&tmp_account_id, &0);
         self.storage_balances.insert(
             &tmp_account_id,
             &StorageBalance {
@@ -261,6 +665,14 @@ impl Contract {
                 available: 0.into(),
             },
         );
+        self.sponsorships.insert(
+            &tmp_account_id,
+            &Sponsorship {
+                sponsor_id: tmp_account_id.clone(),
+                amount: 0.into(),
+                max_accounts: Some(0),
+            },
+        );
         self.metadata.user_storage_usage = (env::storage_usage() - initial_storage_usage).into();
 
         // Calculate storage usage for new account
@@ -270,23 +682,87 @@ impl Contract {
             &Account {
                 owner_id: tmp_account_id.clone(),
                 balance: 0,
+                multisig: None,
+                unlock_at: None,
+                last_activity_at: 0,
             },
         );
         self.user_accounts
-            .insert(&tmp_account_id, &vec![tmp_account_name.clone()]);
+            .insert_account(&tmp_account_id, &tmp_account_name);
         self.metadata.account_storage_usage = (env::storage_usage() - initial_storage_usage).into();
 
         // Clean up
         self.accounts.remove(&tmp_account_name);
+        self.user_accounts
+            .remove_account(&tmp_account_id, &tmp_account_name);
         self.user_accounts.remove(&tmp_account_id);
         self.storage_balances.remove(&tmp_account_id);
+        self.sponsorships.remove(&tmp_account_id);
+        self.user_activity.remove(&tmp_account_id);
     }
 }
 
-#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault, PartialEq, Debug)]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault, Clone, PartialEq, Debug)]
 pub struct Account {
     pub owner_id: AccountId,
     pub balance: Balance,
+
+    // Signers and threshold if the account requires multiple signatures
+    pub multisig: Option<Multisig>,
+
+    // Timestamp in nanoseconds before which tokens cannot leave the account
+    pub unlock_at: Option<u64>,
+
+    // Timestamp in nanoseconds of the last change to the account
+    pub last_activity_at: u64,
+}
+
+impl Account {
+    pub fn is_locked(&self) -> bool {
+        self.unlock_at
+            .is_some_and(|unlock_at| env::block_timestamp() < unlock_at)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountView {
+    pub owner_id: AccountId,
+    pub balance: U128,
+    pub unlock_at: Option<U64>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferQuote {
+    // Fee subtracted from the transferred amount
+    pub transfer_fee: U128,
+
+    // Amount credited to the receiver account
+    pub received_amount: U128,
+
+    // Whether the accounts have different owners
+    pub is_cross_owner: bool,
+
+    // Balances after the transfer
+    pub sender_balance: U128,
+    pub receiver_balance: U128,
+
+    // Error that the transfer would fail with
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferResult {
+    // Account that received the transfer
+    pub receiver_account_name: String,
+
+    // Fee subtracted from the transferred amount
+    pub transfer_fee: U128,
+
+    // Amount credited to the receiver account
+    pub received_amount: U128,
 }
 
 #[derive(
@@ -311,6 +787,10 @@ pub struct ContractMetadata {
     pub transfer_fee_numerator: U128,
     pub transfer_fee_denominator: U128,
 
+    // Share of transfer fee paid to the referrer of the sender
+    pub referral_fee_numerator: U128,
+    pub referral_fee_denominator: U128,
+
     // Storage usage
     pub user_storage_usage: U64,
     pub account_storage_usage: U64,
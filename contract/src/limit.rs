@@ -0,0 +1,370 @@
+use crate::storage::assert_at_least_one_yocto;
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, Promise};
+
+const NANOSECONDS_PER_HOUR: u64 = 3_600_000_000_000;
+const DAY_HOURS: u64 = 24;
+pub const WEEK_HOURS: u64 = 7 * DAY_HOURS;
+
+// Delay before a raised spending limit takes effect
+pub const LIMIT_INCREASE_DELAY: u64 = DAY_HOURS * NANOSECONDS_PER_HOUR;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingSpendingLimit {
+    pub daily_limit: Option<U128>,
+    pub weekly_limit: Option<U128>,
+
+    // Timestamp in nanoseconds when the limits take effect
+    pub effective_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SpendingLimit {
+    // Maximum outflow in any 24 hours and 7 days, unlimited if not set
+    pub daily_limit: Option<Balance>,
+    pub weekly_limit: Option<Balance>,
+
+    // Raised limits waiting for the increase delay
+    pub pending_limit: Option<PendingSpendingLimit>,
+
+    // Outflow of each hour in the last week, indexed by hour modulo WEEK_HOURS
+    pub hourly_outflows: Vec<Balance>,
+
+    // Hour of the most recent outflow
+    pub last_outflow_hour: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SpendingLimitView {
+    pub daily_limit: Option<U128>,
+    pub weekly_limit: Option<U128>,
+    pub pending_limit: Option<PendingSpendingLimit>,
+
+    // Outflow in the last 24 hours and 7 days
+    pub daily_outflow: U128,
+    pub weekly_outflow: U128,
+}
+
+impl SpendingLimit {
+    // Apply pending limits once the increase delay has passed
+    fn apply_pending_limit(&mut self, timestamp: u64) {
+        if let Some(pending_limit) = &self.pending_limit {
+            if timestamp >= pending_limit.effective_at.0 {
+                self.daily_limit = pending_limit.daily_limit.map(|limit| limit.0);
+                self.weekly_limit = pending_limit.weekly_limit.map(|limit| limit.0);
+                self.pending_limit = None;
+            }
+        }
+    }
+
+    // Clear outflows of hours that left the window since the last outflow
+    fn advance(&mut self, hour: u64) {
+        if hour > self.last_outflow_hour {
+            let elapsed_hours = (hour - self.last_outflow_hour).min(WEEK_HOURS);
+            for past_hour in (hour - elapsed_hours + 1)..=hour {
+                self.hourly_outflows[(past_hour % WEEK_HOURS) as usize] = 0;
+            }
+            self.last_outflow_hour = hour;
+        }
+    }
+
+    // Sum outflows in the last number of hours
+    fn outflow(&self, hour: u64, hours: u64) -> Balance {
+        (0..hours)
+            .map(|offset| {
+                self.hourly_outflows[((hour + WEEK_HOURS - offset) % WEEK_HOURS) as usize]
+            })
+            .sum()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Set daily and weekly outflow limits of an account. Lowered limits apply
+    // immediately while raised limits apply after LIMIT_INCREASE_DELAY.
+    #[payable]
+    pub fn set_spending_limit(
+        &mut self,
+        account_name: String,
+        daily_limit: Option<U128>,
+        weekly_limit: Option<U128>,
+    ) {
+        assert_at_least_one_yocto();
+
+        // Get account by account name
+        let account = self
+            .accounts
+            .get(&account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"));
+        require!(
+            account.owner_id == env::signer_account_id(),
+            "Unauthorized access to account"
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        let mut limit = self
+            .spending_limits
+            .get(&account_name)
+            .unwrap_or_else(|| SpendingLimit {
+                daily_limit: None,
+                weekly_limit: None,
+                pending_limit: None,
+                hourly_outflows: vec![0; WEEK_HOURS as usize],
+                last_outflow_hour: env::block_timestamp() / NANOSECONDS_PER_HOUR,
+            });
+        limit.apply_pending_limit(env::block_timestamp());
+
+        // Lowered part of the limits applies immediately
+        let daily_limit = daily_limit.map(|limit| limit.0);
+        let weekly_limit = weekly_limit.map(|limit| limit.0);
+        let is_raised = is_limit_raised(limit.daily_limit, daily_limit)
+            || is_limit_raised(limit.weekly_limit, weekly_limit);
+        limit.daily_limit = min_limit(limit.daily_limit, daily_limit);
+        limit.weekly_limit = min_limit(limit.weekly_limit, weekly_limit);
+        limit.pending_limit = if is_raised {
+            Some(PendingSpendingLimit {
+                daily_limit: daily_limit.map(U128),
+                weekly_limit: weekly_limit.map(U128),
+                effective_at: (env::block_timestamp() + LIMIT_INCREASE_DELAY).into(),
+            })
+        } else {
+            None
+        };
+        self.spending_limits.insert(&account_name, &limit);
+
+        // Owner pays for the outflow history storage
+        if env::storage_usage() >= initial_storage_usage {
+            self.internal_charge_storage_with_deposit(
+                &account.owner_id,
+                initial_storage_usage,
+                env::attached_deposit() - 1,
+            );
+        } else {
+            self.internal_refund_storage(&account.owner_id, initial_storage_usage);
+
+            // Nothing to pay for, so the deposit is refunded
+            if env::attached_deposit() > 1 {
+                Promise::new(env::predecessor_account_id()).transfer(env::attached_deposit() - 1);
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get spending limits and recent outflow of an account
+    pub fn get_spending_limit(&self, account_name: String) -> Option<SpendingLimitView> {
+        self.spending_limits.get(&account_name).map(|mut limit| {
+            let hour = env::block_timestamp() / NANOSECONDS_PER_HOUR;
+            limit.apply_pending_limit(env::block_timestamp());
+            limit.advance(hour);
+            SpendingLimitView {
+                daily_limit: limit.daily_limit.map(U128),
+                weekly_limit: limit.weekly_limit.map(U128),
+                daily_outflow: limit.outflow(hour, DAY_HOURS).into(),
+                weekly_outflow: limit.outflow(hour, WEEK_HOURS).into(),
+                pending_limit: limit.pending_limit,
+            }
+        })
+    }
+}
+
+impl Contract {
+    // Record outflow from an account and check it against the spending limits
+    pub fn internal_record_outflow(
+        &mut self,
+        account_name: &String,
+        owner_id: &AccountId,
+        amount: Balance,
+    ) -> Result<(), &'static str> {
+        let initial_storage_usage = env::storage_usage();
+        if let Some(limit) = self.internal_check_outflow(account_name, amount)? {
+            self.spending_limits.insert(account_name, &limit);
+
+            // Applying pending limits releases storage
+            self.internal_refund_storage(owner_id, initial_storage_usage);
+        }
+        Ok(())
+    }
+
+    // Check outflow from an account against the spending limits and get the
+    // limits with the outflow recorded, without writing them
+    pub fn internal_check_outflow(
+        &self,
+        account_name: &String,
+        amount: Balance,
+    ) -> Result<Option<SpendingLimit>, &'static str> {
+        let mut limit = match self.spending_limits.get(account_name) {
+            Some(limit) => limit,
+            None => return Ok(None),
+        };
+
+        let hour = env::block_timestamp() / NANOSECONDS_PER_HOUR;
+        limit.apply_pending_limit(env::block_timestamp());
+        limit.advance(hour);
+
+        let bucket = (hour % WEEK_HOURS) as usize;
+        limit.hourly_outflows[bucket] = limit.hourly_outflows[bucket]
+            .checked_add(amount)
+            .ok_or("Balance overflow")?;
+        if let Some(daily_limit) = limit.daily_limit {
+            if limit.outflow(hour, DAY_HOURS) > daily_limit {
+                return Err("Daily spending limit exceeded");
+            }
+        }
+        if let Some(weekly_limit) = limit.weekly_limit {
+            if limit.outflow(hour, WEEK_HOURS) > weekly_limit {
+                return Err("Weekly spending limit exceeded");
+            }
+        }
+        Ok(Some(limit))
+    }
+}
+
+// Whether the new limit is higher than the current one, where None is unlimited
+fn is_limit_raised(current: Option<Balance>, new: Option<Balance>) -> bool {
+    match (current, new) {
+        (_, None) => current.is_some(),
+        (None, Some(_)) => false,
+        (Some(current), Some(new)) => new > current,
+    }
+}
+
+fn min_limit(current: Option<Balance>, new: Option<Balance>) -> Option<Balance> {
+    match (current, new) {
+        (Some(current), Some(new)) => Some(current.min(new)),
+        (current, None) => current,
+        (None, new) => new,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        setup_user(&mut contract, &accounts(1), &["account_1", "account_2"]);
+        contract.internal_deposit("account_1".to_owned(), 1000.into());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_spending_limit("account_1".to_owned(), Some(100.into()), Some(300.into()));
+        contract
+    }
+
+    fn withdraw(contract: &mut Contract, amount: u128, block_timestamp: u64) {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(block_timestamp)
+            .build());
+        contract.withdraw("account_1".to_owned(), amount.into());
+    }
+
+    #[test]
+    fn test_spending_limit() {
+        let mut contract = setup_contract();
+
+        withdraw(&mut contract, 60, 0);
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.transfer("account_1".to_owned(), "account_2".to_owned(), 40.into());
+
+        // Daily window rolls over after 24 hours
+        withdraw(&mut contract, 100, DAY_HOURS * NANOSECONDS_PER_HOUR);
+        let view = contract.get_spending_limit("account_1".to_owned()).unwrap();
+        assert_eq!(view.daily_outflow, 100.into());
+        assert_eq!(view.weekly_outflow, 200.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Daily spending limit exceeded")]
+    fn test_daily_spending_limit_exceeded() {
+        let mut contract = setup_contract();
+
+        withdraw(&mut contract, 60, 0);
+        withdraw(&mut contract, 60, (DAY_HOURS - 1) * NANOSECONDS_PER_HOUR);
+    }
+
+    #[test]
+    fn test_transfer_quote_over_spending_limit() {
+        let mut contract = setup_contract();
+        withdraw(&mut contract, 60, 0);
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.is_view(true).build());
+        let quote =
+            contract.get_transfer_quote("account_1".to_owned(), "account_2".to_owned(), 41.into());
+        assert_eq!(
+            quote.error,
+            Some("Daily spending limit exceeded".to_owned())
+        );
+
+        // Quoting does not record the outflow
+        let view = contract.get_spending_limit("account_1".to_owned()).unwrap();
+        assert_eq!(view.daily_outflow, 60.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Weekly spending limit exceeded")]
+    fn test_weekly_spending_limit_exceeded() {
+        let mut contract = setup_contract();
+
+        for day in 0..4 {
+            withdraw(&mut contract, 100, day * DAY_HOURS * NANOSECONDS_PER_HOUR);
+        }
+    }
+
+    #[test]
+    fn test_raise_spending_limit_is_delayed() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_spending_limit("account_1".to_owned(), Some(200.into()), Some(300.into()));
+
+        let view = contract.get_spending_limit("account_1".to_owned()).unwrap();
+        assert_eq!(view.daily_limit, Some(100.into()));
+        assert_eq!(
+            view.pending_limit.unwrap().effective_at,
+            LIMIT_INCREASE_DELAY.into()
+        );
+
+        withdraw(&mut contract, 200, LIMIT_INCREASE_DELAY);
+        let view = contract.get_spending_limit("account_1".to_owned()).unwrap();
+        assert_eq!(view.daily_limit, Some(200.into()));
+        assert!(view.pending_limit.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Daily spending limit exceeded")]
+    fn test_raise_spending_limit_before_delay() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_spending_limit("account_1".to_owned(), None, None);
+
+        withdraw(&mut contract, 200, LIMIT_INCREASE_DELAY - 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Daily spending limit exceeded")]
+    fn test_lower_spending_limit_is_immediate() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_spending_limit("account_1".to_owned(), Some(10.into()), Some(300.into()));
+
+        withdraw(&mut contract, 20, 0);
+    }
+}
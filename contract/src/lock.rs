@@ -0,0 +1,241 @@
+use crate::storage::assert_at_least_one_yocto;
+use crate::{Contract, ContractExt};
+use near_sdk::json_types::U64;
+use near_sdk::{env, near_bindgen, require, AccountId, Balance};
+
+// Maximum time in nanoseconds an account can be locked for from now (5 years)
+pub const MAX_LOCK_DURATION: u64 = 5 * 365 * 24 * 60 * 60 * 1_000_000_000;
+
+#[near_bindgen]
+impl Contract {
+    // Create new account that rejects outflows until unlock_at
+    #[payable]
+    pub fn create_locked_account(
+        &mut self,
+        account_name: String,
+        unlock_at: U64,
+        referrer_id: Option<AccountId>,
+    ) {
+        require!(
+            unlock_at.0 > env::block_timestamp(),
+            "Unlock time must be in the future"
+        );
+        assert_lock_duration(unlock_at.0);
+        self.create_account(account_name.clone(), referrer_id);
+        self.internal_set_lock(&account_name, unlock_at.0, 0);
+    }
+
+    // Extend the lock of an account to a later unlock_at
+    #[payable]
+    pub fn extend_lock(&mut self, account_name: String, unlock_at: U64) {
+        assert_at_least_one_yocto();
+        let account = self
+            .accounts
+            .get(&account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"));
+        require!(
+            account.owner_id == env::signer_account_id(),
+            "Unauthorized access to account"
+        );
+        require!(
+            account.multisig.is_none(),
+            "Multi-signature account requires a proposal"
+        );
+        self.internal_assert_not_guarded(&account_name);
+        self.internal_extend_lock(&account_name, unlock_at.0, env::attached_deposit() - 1);
+    }
+}
+
+impl Contract {
+    pub fn internal_extend_lock(
+        &mut self,
+        account_name: &String,
+        unlock_at: u64,
+        deposit: Balance,
+    ) {
+        let current_unlock_at = self
+            .accounts
+            .get(account_name)
+            .unwrap()
+            .unlock_at
+            .unwrap_or_else(|| panic!("Account is not a locked account"));
+        require!(unlock_at > current_unlock_at, "Lock can only be extended");
+        assert_lock_duration(unlock_at);
+        self.internal_set_lock(account_name, unlock_at, deposit);
+    }
+
+    fn internal_set_lock(&mut self, account_name: &String, unlock_at: u64, deposit: Balance) {
+        let mut account = self.accounts.get(account_name).unwrap();
+
+        // Owner pays for the lock storage
+        let initial_storage_usage = env::storage_usage();
+        account.unlock_at = Some(unlock_at);
+        self.accounts.insert(account_name, &account);
+        self.internal_charge_storage_with_deposit(
+            &account.owner_id,
+            initial_storage_usage,
+            deposit,
+        );
+    }
+}
+
+fn assert_lock_duration(unlock_at: u64) {
+    require!(
+        unlock_at <= env::block_timestamp() + MAX_LOCK_DURATION,
+        "Unlock time is too far in the future"
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use crate::AccountView;
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    const UNLOCK_AT: u64 = 1_000_000_000;
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        setup_user(&mut contract, &accounts(1), &[]);
+        contract.create_locked_account("savings".to_owned(), UNLOCK_AT.into(), None);
+        contract.create_account("spending".to_owned(), None);
+
+        // Deposits are accepted while the account is locked
+        contract.internal_deposit("savings".to_owned(), 100.into());
+        contract
+    }
+
+    #[test]
+    fn test_create_locked_account() {
+        let contract = setup_contract();
+
+        assert_eq!(
+            contract.get_account("savings".to_owned()).unwrap(),
+            AccountView {
+                owner_id: accounts(1),
+                balance: 100.into(),
+                unlock_at: Some(UNLOCK_AT.into()),
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is locked")]
+    fn test_withdraw_locked_account() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(UNLOCK_AT - 1)
+            .build());
+        contract.withdraw("savings".to_owned(), 1.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is locked")]
+    fn test_transfer_locked_account() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(UNLOCK_AT - 1).build());
+        contract.transfer("savings".to_owned(), "spending".to_owned(), 1.into());
+    }
+
+    #[test]
+    fn test_transfer_into_locked_account() {
+        let mut contract = setup_contract();
+        contract.internal_deposit("spending".to_owned(), 10.into());
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.transfer("spending".to_owned(), "savings".to_owned(), 10.into());
+        assert_eq!(
+            contract.get_balance("savings".to_owned()).unwrap(),
+            110.into()
+        );
+    }
+
+    #[test]
+    fn test_withdraw_unlocked_account() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(UNLOCK_AT)
+            .build());
+        contract.withdraw("savings".to_owned(), 100.into());
+        assert_eq!(
+            contract.get_balance("savings".to_owned()).unwrap(),
+            0.into()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is locked")]
+    fn test_extend_lock() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.extend_lock("savings".to_owned(), (UNLOCK_AT * 2).into());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(UNLOCK_AT)
+            .build());
+        contract.withdraw("savings".to_owned(), 1.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of at least 1 yoctoNEAR")]
+    fn test_extend_lock_without_deposit() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.extend_lock("savings".to_owned(), (UNLOCK_AT * 2).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Lock can only be extended")]
+    fn test_shorten_lock() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.extend_lock("savings".to_owned(), (UNLOCK_AT - 1).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unlock time is too far in the future")]
+    fn test_extend_lock_beyond_max_duration() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.extend_lock("savings".to_owned(), (MAX_LOCK_DURATION + 1).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Multi-signature account requires a proposal")]
+    fn test_extend_multisig_lock() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.set_multisig(
+            "savings".to_owned(),
+            vec![accounts(1), accounts(2)],
+            2,
+            UNLOCK_AT.into(),
+        );
+        contract.extend_lock("savings".to_owned(), (UNLOCK_AT * 2).into());
+    }
+}
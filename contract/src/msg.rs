@@ -10,3 +10,9 @@ pub struct TransferMessage {
 pub struct DepositPayload {
     pub account_name: String,
 }
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RegisterPayload {
+    // Name of the account created for the sender
+    pub account_name: String,
+}
@@ -0,0 +1,556 @@
+use crate::storage::assert_at_least_one_yocto;
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId, Promise, StorageUsage};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Multisig {
+    // Account IDs allowed to propose and confirm
+    pub signers: Vec<AccountId>,
+
+    // Number of confirmations required to execute a proposal
+    pub threshold: u32,
+
+    // Duration in nanoseconds before a proposal expires
+    pub proposal_lifetime: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ProposalKind {
+    // Withdraw tokens from the account to a NEAR account
+    Withdraw {
+        receiver_id: AccountId,
+        amount: U128,
+    },
+
+    // Transfer tokens from the account to another account
+    Transfer {
+        receiver_account_name: String,
+        amount: U128,
+    },
+
+    // Extend the lock of the account to a later unlock_at
+    ExtendLock {
+        unlock_at: U64,
+    },
+
+    // Appoint a guardian for the account
+    SetGuardian {
+        guardian_id: AccountId,
+    },
+
+    // Remove the guardian of the account
+    RemoveGuardian,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Proposal {
+    pub id: U64,
+
+    // Multi-signature account the proposal spends from
+    pub account_name: String,
+
+    // Signer who created the proposal and paid for its storage
+    pub proposer: AccountId,
+
+    pub kind: ProposalKind,
+
+    // Signers who confirmed the proposal, including the proposer
+    pub confirmations: Vec<AccountId>,
+
+    // Timestamp in nanoseconds after which the proposal cannot be confirmed
+    pub expires_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Turn an account into a multi-signature account
+    #[payable]
+    pub fn set_multisig(
+        &mut self,
+        account_name: String,
+        signers: Vec<AccountId>,
+        threshold: u32,
+        proposal_lifetime: U64,
+    ) {
+        assert_at_least_one_yocto();
+
+        // Get account by account name
+        let mut account = self
+            .accounts
+            .get(&account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"));
+        require!(
+            account.owner_id == env::signer_account_id(),
+            "Unauthorized access to account"
+        );
+        require!(
+            account.multisig.is_none(),
+            "Account is already a multi-signature account"
+        );
+        self.internal_assert_not_guarded(&account_name);
+
+        // Signers must be unique and able to reach the threshold
+        let mut unique_signers = signers.clone();
+        unique_signers.sort();
+        unique_signers.dedup();
+        require!(
+            unique_signers.len() == signers.len(),
+            "Duplicate multi-signature signer"
+        );
+        require!(
+            threshold > 0 && threshold as usize <= signers.len(),
+            "Invalid multi-signature threshold"
+        );
+        require!(proposal_lifetime.0 > 0, "Invalid proposal lifetime");
+
+        // Owner pays for the signer list storage
+        let initial_storage_usage = env::storage_usage();
+        account.multisig = Some(Multisig {
+            signers,
+            threshold,
+            proposal_lifetime,
+        });
+        self.accounts.insert(&account_name, &account);
+        self.internal_charge_storage_with_deposit(
+            &account.owner_id,
+            initial_storage_usage,
+            env::attached_deposit() - 1,
+        );
+    }
+
+    // Propose an action on a multi-signature account
+    #[payable]
+    pub fn create_proposal(&mut self, account_name: String, kind: ProposalKind) -> Option<Promise> {
+        let proposer = env::signer_account_id();
+        let multisig = self.internal_get_multisig(&account_name);
+        require!(
+            multisig.signers.contains(&proposer),
+            "Unauthorized access to account"
+        );
+        self.internal_remove_expired_proposals(&account_name);
+
+        // Proposer pays for the proposal storage
+        let initial_storage_usage = env::storage_usage();
+        let id = self.next_proposal_id;
+        self.next_proposal_id += 1;
+        let proposal = Proposal {
+            id: id.into(),
+            account_name: account_name.clone(),
+            proposer: proposer.clone(),
+            kind,
+            confirmations: vec![proposer.clone()],
+            expires_at: (env::block_timestamp() + multisig.proposal_lifetime.0).into(),
+        };
+        self.proposals.insert(&id, &proposal);
+        let mut account_proposals = self
+            .account_proposals
+            .get(&account_name)
+            .unwrap_or_default();
+        account_proposals.push(id);
+        self.account_proposals
+            .insert(&account_name, &account_proposals);
+        self.internal_charge_storage_with_deposit(
+            &proposer,
+            initial_storage_usage,
+            env::attached_deposit(),
+        );
+
+        // Proposal is executed right away with a threshold of one
+        if multisig.threshold == 1 {
+            self.internal_execute_proposal(proposal)
+        } else {
+            None
+        }
+    }
+
+    // Confirm a proposal and execute it once the threshold is reached
+    #[payable]
+    pub fn confirm_proposal(&mut self, proposal_id: U64) -> Option<Promise> {
+        let signer_id = env::signer_account_id();
+        let mut proposal = self
+            .proposals
+            .get(&proposal_id.0)
+            .unwrap_or_else(|| panic!("Proposal does not exist"));
+        let multisig = self.internal_get_multisig(&proposal.account_name);
+        require!(
+            multisig.signers.contains(&signer_id),
+            "Unauthorized access to account"
+        );
+        require!(
+            env::block_timestamp() <= proposal.expires_at.0,
+            "Proposal has expired"
+        );
+        require!(
+            !proposal.confirmations.contains(&signer_id),
+            "Proposal already confirmed"
+        );
+
+        proposal.confirmations.push(signer_id.clone());
+        if proposal.confirmations.len() >= multisig.threshold as usize {
+            // Nothing to pay for, so the deposit is refunded
+            if env::attached_deposit() > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(env::attached_deposit());
+            }
+            self.internal_execute_proposal(proposal)
+        } else {
+            // Signer pays for the confirmation storage
+            let initial_storage_usage = env::storage_usage();
+            self.proposals.insert(&proposal_id.0, &proposal);
+            self.internal_charge_storage_with_deposit(
+                &signer_id,
+                initial_storage_usage,
+                env::attached_deposit(),
+            );
+            None
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get multi-signature settings of an account
+    pub fn get_multisig(&self, account_name: String) -> Option<Multisig> {
+        self.accounts
+            .get(&account_name)
+            .and_then(|account| account.multisig)
+    }
+
+    // Get proposal by ID
+    pub fn get_proposal(&self, proposal_id: U64) -> Option<Proposal> {
+        self.proposals.get(&proposal_id.0)
+    }
+
+    // Get list of proposals of an account that have not expired
+    pub fn get_pending_proposals(&self, account_name: String) -> Vec<Proposal> {
+        self.account_proposals
+            .get(&account_name)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|id| self.proposals.get(id))
+            .filter(|proposal| env::block_timestamp() <= proposal.expires_at.0)
+            .collect()
+    }
+}
+
+impl Contract {
+    fn internal_get_multisig(&self, account_name: &String) -> Multisig {
+        self.accounts
+            .get(account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"))
+            .multisig
+            .unwrap_or_else(|| panic!("Account is not a multi-signature account"))
+    }
+
+    fn internal_execute_proposal(&mut self, proposal: Proposal) -> Option<Promise> {
+        self.internal_remove_proposal(&proposal);
+
+        match proposal.kind {
+            ProposalKind::Withdraw {
+                receiver_id,
+                amount,
+            } => {
+                let mut account = self.accounts.get(&proposal.account_name).unwrap();
+                account.balance = account
+                    .balance
+                    .checked_sub(amount.into())
+                    .unwrap_or_else(|| panic!("Balance overflow"));
+                self.accounts.insert(&proposal.account_name, &account);
+                self.internal_before_outflow(&proposal.account_name, &account, amount.into());
+
+                Some(self.internal_ft_transfer(proposal.account_name, receiver_id, amount, None))
+            }
+            ProposalKind::Transfer {
+                receiver_account_name,
+                amount,
+            } => {
+                let (sender_account, receiver_account, transfer_fee) = self
+                    .internal_prepare_transfer(
+                        &proposal.account_name,
+                        &receiver_account_name,
+                        amount.into(),
+                        None,
+                    )
+                    .unwrap_or_else(|err| panic!("{}", err));
+                self.internal_collect_transfer_fee(&sender_account.owner_id, transfer_fee);
+                self.internal_before_outflow(
+                    &proposal.account_name,
+                    &sender_account,
+                    amount.into(),
+                );
+
+                self.accounts
+                    .insert(&proposal.account_name, &sender_account);
+                self.accounts
+                    .insert(&receiver_account_name, &receiver_account);
+                None
+            }
+            ProposalKind::ExtendLock { unlock_at } => {
+                // Owner pays for the lock storage from their storage balance
+                self.internal_extend_lock(&proposal.account_name, unlock_at.0, 0);
+                None
+            }
+            ProposalKind::SetGuardian { guardian_id } => {
+                // Owner pays for the guardian storage from their storage balance
+                self.internal_set_guardian(&proposal.account_name, guardian_id, 0);
+                None
+            }
+            ProposalKind::RemoveGuardian => {
+                self.internal_remove_guardian(&proposal.account_name);
+                None
+            }
+        }
+    }
+
+    // Remove proposal and refund its storage to the proposer and the signers
+    // who paid for their confirmations
+    fn internal_remove_proposal(&mut self, proposal: &Proposal) {
+        let initial_storage_usage = env::storage_usage();
+        let stored_confirmations = self
+            .proposals
+            .remove(&proposal.id.0)
+            .map(|proposal| proposal.confirmations)
+            .unwrap_or_default();
+        if let Some(mut account_proposals) = self.account_proposals.get(&proposal.account_name) {
+            account_proposals.retain(|id| *id != proposal.id.0);
+            if account_proposals.is_empty() {
+                self.account_proposals.remove(&proposal.account_name);
+            } else {
+                self.account_proposals
+                    .insert(&proposal.account_name, &account_proposals);
+            }
+        }
+
+        let mut confirmation_storage_usage = 0;
+        for signer_id in stored_confirmations
+            .iter()
+            .filter(|signer_id| *signer_id != &proposal.proposer)
+        {
+            let storage_usage = signer_id.try_to_vec().unwrap().len() as StorageUsage;
+            self.internal_refund_storage(signer_id, env::storage_usage() + storage_usage);
+            confirmation_storage_usage += storage_usage;
+        }
+        self.internal_refund_storage(
+            &proposal.proposer,
+            initial_storage_usage - confirmation_storage_usage,
+        );
+    }
+
+    fn internal_remove_expired_proposals(&mut self, account_name: &String) {
+        for id in self.account_proposals.get(account_name).unwrap_or_default() {
+            if let Some(proposal) = self.proposals.get(&id) {
+                if env::block_timestamp() > proposal.expires_at.0 {
+                    self.internal_remove_proposal(&proposal);
+                }
+            }
+        }
+    }
+
+    // Remove all proposals of an account
+    pub fn internal_remove_account_proposals(&mut self, account_name: &String) {
+        for id in self.account_proposals.get(account_name).unwrap_or_default() {
+            if let Some(proposal) = self.proposals.get(&id) {
+                self.internal_remove_proposal(&proposal);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    const PROPOSAL_LIFETIME: u64 = 1_000_000_000;
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        for account_id in [accounts(1), accounts(3), accounts(4)] {
+            setup_user(&mut contract, &account_id, &[account_id.as_str()]);
+        }
+        contract.internal_deposit(accounts(1).to_string(), 100.into());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_multisig(
+            accounts(1).to_string(),
+            vec![accounts(1), accounts(3), accounts(4)],
+            2,
+            PROPOSAL_LIFETIME.into(),
+        );
+        contract
+    }
+
+    fn transfer_proposal(amount: u128) -> ProposalKind {
+        ProposalKind::Transfer {
+            receiver_account_name: accounts(3).to_string(),
+            amount: amount.into(),
+        }
+    }
+
+    #[test]
+    fn test_multisig_transfer() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.create_proposal(accounts(1).to_string(), transfer_proposal(100));
+        assert_eq!(
+            contract
+                .get_pending_proposals(accounts(1).to_string())
+                .len(),
+            1
+        );
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            100.into()
+        );
+
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        contract.confirm_proposal(0.into());
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            0.into()
+        );
+        assert_eq!(
+            contract.get_balance(accounts(3).to_string()).unwrap(),
+            99.into()
+        );
+        assert!(contract.get_proposal(0.into()).is_none());
+        assert!(contract
+            .get_pending_proposals(accounts(1).to_string())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_multisig_withdraw() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.create_proposal(
+            accounts(1).to_string(),
+            ProposalKind::Withdraw {
+                receiver_id: accounts(5),
+                amount: 40.into(),
+            },
+        );
+
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        assert!(contract.confirm_proposal(0.into()).is_some());
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            60.into()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Multi-signature account requires a proposal")]
+    fn test_multisig_direct_withdraw() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw(accounts(1).to_string(), 1.into());
+    }
+
+    #[test]
+    fn test_multisig_transfer_quote() {
+        let contract = setup_contract();
+
+        assert_eq!(
+            contract
+                .get_transfer_quote(accounts(1).to_string(), accounts(3).to_string(), 1.into())
+                .error,
+            Some("Multi-signature account requires a proposal".to_owned())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Multi-signature account requires a proposal")]
+    fn test_multisig_direct_transfer() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.transfer(accounts(1).to_string(), accounts(3).to_string(), 1.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Proposal already confirmed")]
+    fn test_confirm_proposal_twice() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.create_proposal(accounts(1).to_string(), transfer_proposal(1));
+        contract.confirm_proposal(0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access to account")]
+    fn test_confirm_proposal_not_signer() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.create_proposal(accounts(1).to_string(), transfer_proposal(1));
+
+        let context = get_context(accounts(5));
+        testing_env!(context.build());
+        contract.confirm_proposal(0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Proposal has expired")]
+    fn test_confirm_expired_proposal() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.create_proposal(accounts(1).to_string(), transfer_proposal(1));
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.block_timestamp(PROPOSAL_LIFETIME + 1).build());
+        assert!(contract
+            .get_pending_proposals(accounts(1).to_string())
+            .is_empty());
+        contract.confirm_proposal(0.into());
+    }
+
+    #[test]
+    fn test_expired_proposal_storage_refund() {
+        let mut contract = setup_contract();
+        let storage_balance = contract.storage_balance_of(accounts(1)).unwrap();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.create_proposal(accounts(1).to_string(), transfer_proposal(1));
+        assert!(
+            contract
+                .storage_balance_of(accounts(1))
+                .unwrap()
+                .available
+                .0
+                < storage_balance.available.0
+        );
+
+        // Expired proposal is removed when a new proposal is created
+        let mut context = get_context(accounts(3));
+        testing_env!(context.block_timestamp(PROPOSAL_LIFETIME + 1).build());
+        contract.create_proposal(accounts(1).to_string(), transfer_proposal(1));
+        assert!(contract.get_proposal(0.into()).is_none());
+        assert_eq!(
+            contract.storage_balance_of(accounts(1)).unwrap().available,
+            storage_balance.available
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::event::emit_event;
+use crate::{Contract, ContractExt};
+use near_sdk::serde_json::json;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+#[near_bindgen]
+impl Contract {
+    // Propose a new contract owner, who must accept the ownership
+    #[payable]
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        assert_one_yocto();
+        self.internal_assert_contract_owner();
+        require!(
+            new_owner != self.metadata.owner_id,
+            "New owner is the current owner"
+        );
+
+        self.proposed_owner_id = Some(new_owner.clone());
+        emit_event(
+            "propose_owner",
+            json!({ "owner_id": self.metadata.owner_id, "proposed_owner_id": new_owner }),
+        );
+    }
+
+    // Cancel the pending ownership proposal
+    #[payable]
+    pub fn cancel_owner_proposal(&mut self) {
+        assert_one_yocto();
+        self.internal_assert_contract_owner();
+        let proposed_owner_id = self
+            .proposed_owner_id
+            .take()
+            .unwrap_or_else(|| panic!("No ownership proposal"));
+        emit_event(
+            "cancel_owner_proposal",
+            json!({ "owner_id": self.metadata.owner_id, "proposed_owner_id": proposed_owner_id }),
+        );
+    }
+
+    // Accept the contract ownership proposed to the caller
+    #[payable]
+    pub fn accept_ownership(&mut self) {
+        assert_one_yocto();
+        require!(
+            self.proposed_owner_id.as_ref() == Some(&env::signer_account_id()),
+            "Unauthorized access"
+        );
+
+        let old_owner_id = std::mem::replace(
+            &mut self.metadata.owner_id,
+            self.proposed_owner_id.take().unwrap(),
+        );
+        emit_event(
+            "transfer_ownership",
+            json!({ "old_owner_id": old_owner_id, "new_owner_id": self.metadata.owner_id }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get the account ID proposed as the next contract owner
+    pub fn get_proposed_owner(&self) -> Option<AccountId> {
+        self.proposed_owner_id.clone()
+    }
+}
+
+impl Contract {
+    pub fn internal_assert_contract_owner(&self) {
+        require!(
+            env::signer_account_id() == self.metadata.owner_id,
+            "Unauthorized access"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::role::Role;
+    use crate::test::tests::{get_context, new_contract};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.total_transfer_fee = 10;
+        contract.propose_owner(accounts(3));
+        contract
+    }
+
+    #[test]
+    fn test_accept_ownership() {
+        let mut contract = setup_contract();
+        assert_eq!(contract.get_proposed_owner(), Some(accounts(3)));
+
+        testing_env!(get_context(accounts(3)).attached_deposit(1).build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_metadata().owner_id, accounts(3));
+        assert_eq!(contract.get_proposed_owner(), None);
+        assert_eq!(contract.get_roles(accounts(3)), vec![Role::Admin]);
+
+        // Fee withdrawal follows the new owner
+        contract.withdraw_transfer_fee(10.into());
+        assert_eq!(contract.total_transfer_fee, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_withdraw_transfer_fee_by_old_owner() {
+        let mut contract = setup_contract();
+
+        testing_env!(get_context(accounts(3)).attached_deposit(1).build());
+        contract.accept_ownership();
+
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.withdraw_transfer_fee(10.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_accept_cancelled_ownership() {
+        let mut contract = setup_contract();
+        contract.cancel_owner_proposal();
+
+        testing_env!(get_context(accounts(3)).attached_deposit(1).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_accept_ownership_by_other_account() {
+        let mut contract = setup_contract();
+
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.accept_ownership();
+    }
+}
@@ -0,0 +1,355 @@
+use crate::event::emit_event;
+use crate::role::Role;
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde", default)]
+pub struct PauseFlags {
+    // Token deposits through ft_on_transfer
+    pub deposits: bool,
+
+    // Token withdrawals to NEAR accounts
+    pub withdrawals: bool,
+
+    // Transfers between accounts
+    pub transfers: bool,
+
+    // Creation of new accounts
+    pub account_creation: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Appoint the pause guardian who can pause but not unpause
+    #[payable]
+    pub fn set_pause_guardian(&mut self, guardian_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.internal_assert_role(Role::Admin);
+        self.pause_guardian = guardian_id;
+        emit_event(
+            "set_pause_guardian",
+            json!({ "guardian_id": self.pause_guardian }),
+        );
+    }
+
+    // Pause the operations set in flags
+    pub fn pause(&mut self, flags: PauseFlags) {
+        let signer_id = env::signer_account_id();
+        require!(
+            self.internal_has_role(&signer_id, Role::Pauser)
+                || self.pause_guardian.as_ref() == Some(&signer_id),
+            "Unauthorized access"
+        );
+
+        self.paused.deposits |= flags.deposits;
+        self.paused.withdrawals |= flags.withdrawals;
+        self.paused.transfers |= flags.transfers;
+        self.paused.account_creation |= flags.account_creation;
+        emit_event("pause", json!({ "by": signer_id, "paused": self.paused }));
+    }
+
+    // Resume the operations set in flags
+    #[payable]
+    pub fn unpause(&mut self, flags: PauseFlags) {
+        assert_one_yocto();
+        self.internal_assert_role(Role::Admin);
+
+        self.paused.deposits &= !flags.deposits;
+        self.paused.withdrawals &= !flags.withdrawals;
+        self.paused.transfers &= !flags.transfers;
+        self.paused.account_creation &= !flags.account_creation;
+        emit_event(
+            "unpause",
+            json!({ "by": env::signer_account_id(), "paused": self.paused }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get operations that are currently paused
+    pub fn get_paused(&self) -> PauseFlags {
+        self.paused.clone()
+    }
+
+    // Get the pause guardian
+    pub fn get_pause_guardian(&self) -> Option<AccountId> {
+        self.pause_guardian.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msg::{DepositPayload, TransferMessage};
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::borsh::BorshSerialize;
+    use near_sdk::{
+        bs58,
+        test_utils::{accounts, get_logs},
+        testing_env,
+    };
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        setup_user(&mut contract, &accounts(1), &["alice", "bob"]);
+        contract.internal_deposit("alice".to_owned(), 100.into());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_pause_guardian(Some(accounts(3)));
+        contract
+    }
+
+    fn deposit_message(account_name: &str) -> String {
+        let payload = DepositPayload {
+            account_name: account_name.to_owned(),
+        };
+        let message = TransferMessage {
+            action: "deposit".to_owned(),
+            payload: payload.try_to_vec().unwrap(),
+        };
+        bs58::encode(message.try_to_vec().unwrap()).into_string()
+    }
+
+    #[test]
+    fn test_pause_by_guardian() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.pause(PauseFlags {
+            transfers: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            contract.get_paused(),
+            PauseFlags {
+                transfers: true,
+                ..Default::default()
+            }
+        );
+        assert!(get_logs()[0].starts_with("EVENT_JSON:"));
+        assert_eq!(
+            contract
+                .get_transfer_quote("alice".to_owned(), "bob".to_owned(), 1.into())
+                .error,
+            Some("Transfers are paused".to_owned())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_unpause_by_guardian() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.pause(PauseFlags {
+            transfers: true,
+            ..Default::default()
+        });
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.unpause(PauseFlags {
+            transfers: true,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn test_unpause_by_owner() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.pause(PauseFlags {
+            transfers: true,
+            withdrawals: true,
+            ..Default::default()
+        });
+        testing_env!(context.attached_deposit(1).build());
+        contract.unpause(PauseFlags {
+            transfers: true,
+            ..Default::default()
+        });
+
+        testing_env!(context.attached_deposit(0).build());
+        contract.transfer("alice".to_owned(), "bob".to_owned(), 10.into());
+        assert!(contract.get_paused().withdrawals);
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposits are paused")]
+    fn test_deposit_paused() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.pause(PauseFlags {
+            deposits: true,
+            ..Default::default()
+        });
+
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        contract.ft_on_transfer(accounts(1), 10.into(), deposit_message("alice"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Withdrawals are paused")]
+    fn test_withdraw_paused() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.pause(PauseFlags {
+            withdrawals: true,
+            ..Default::default()
+        });
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_to("alice".to_owned(), accounts(4), 10.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account creation is paused")]
+    fn test_create_account_paused() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.pause(PauseFlags {
+            account_creation: true,
+            ..Default::default()
+        });
+        contract.create_account("carol".to_owned(), None);
+    }
+
+    fn pause_transfers(contract: &mut Contract) {
+        testing_env!(get_context(accounts(3)).build());
+        contract.pause(PauseFlags {
+            transfers: true,
+            ..Default::default()
+        });
+    }
+
+    // Call as the owner of alice and bob with enough deposit for any storage
+    fn set_owner_context() {
+        testing_env!(get_context(accounts(1))
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers are paused")]
+    fn test_create_escrow_paused() {
+        let mut contract = setup_contract();
+        pause_transfers(&mut contract);
+
+        set_owner_context();
+        contract.create_escrow(
+            "alice".to_owned(),
+            "bob".to_owned(),
+            10.into(),
+            accounts(4),
+            1.into(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers are paused")]
+    fn test_release_paused() {
+        let mut contract = setup_contract();
+        set_owner_context();
+        let escrow_id = contract.create_escrow(
+            "alice".to_owned(),
+            "bob".to_owned(),
+            10.into(),
+            accounts(4),
+            1.into(),
+        );
+        pause_transfers(&mut contract);
+
+        testing_env!(get_context(accounts(1)).build());
+        contract.release(escrow_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers are paused")]
+    fn test_lock_htlc_paused() {
+        let mut contract = setup_contract();
+        pause_transfers(&mut contract);
+
+        set_owner_context();
+        contract.lock_htlc(
+            "alice".to_owned(),
+            "bob".to_owned(),
+            10.into(),
+            env::sha256(b"secret").into(),
+            1.into(),
+        );
+    }
+
+    #[test]
+    fn test_claim_htlc_while_paused() {
+        let mut contract = setup_contract();
+        set_owner_context();
+        let htlc_id = contract.lock_htlc(
+            "alice".to_owned(),
+            "bob".to_owned(),
+            10.into(),
+            env::sha256(b"secret").into(),
+            1.into(),
+        );
+        pause_transfers(&mut contract);
+
+        testing_env!(get_context(accounts(4)).build());
+        contract.claim_htlc(htlc_id, b"secret".to_vec().into());
+        assert!(contract.get_htlc(htlc_id).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers are paused")]
+    fn test_create_vesting_paused() {
+        let mut contract = setup_contract();
+        pause_transfers(&mut contract);
+
+        set_owner_context();
+        contract.create_vesting(
+            "alice".to_owned(),
+            "bob".to_owned(),
+            10.into(),
+            0.into(),
+            0.into(),
+            1.into(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers are paused")]
+    fn test_claim_vesting_paused() {
+        let mut contract = setup_contract();
+        set_owner_context();
+        let vesting_id = contract.create_vesting(
+            "alice".to_owned(),
+            "bob".to_owned(),
+            10.into(),
+            0.into(),
+            0.into(),
+            1.into(),
+        );
+        pause_transfers(&mut contract);
+
+        testing_env!(get_context(accounts(1)).block_timestamp(1).build());
+        contract.claim_vesting(vesting_id);
+    }
+}
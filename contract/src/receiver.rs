@@ -1,4 +1,4 @@
-use crate::msg::{DepositPayload, TransferMessage};
+use crate::msg::{DepositPayload, RegisterPayload, TransferMessage};
 use crate::{Contract, ContractExt};
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
 use near_sdk::borsh::BorshDeserialize;
@@ -8,7 +8,6 @@ use near_sdk::{bs58, env, near_bindgen, require, AccountId, PromiseOrValue};
 #[near_bindgen]
 impl FungibleTokenReceiver for Contract {
     // Receiver for NEP-141 token transfer
-    #[allow(unused_variables)]
     fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
@@ -20,6 +19,7 @@ impl FungibleTokenReceiver for Contract {
             env::predecessor_account_id() == self.metadata.token_id,
             "Unsupported token type"
         );
+        require!(!self.paused.deposits, "Deposits are paused");
 
         // Parse JSON message into TransferMessage and match each action
         let decoded_message = bs58::decode(&msg)
@@ -36,6 +36,14 @@ impl FungibleTokenReceiver for Contract {
                 // Return 0 as we transfer all the tokens to the account
                 PromiseOrValue::Value(0.into())
             }
+            "register" => {
+                let payload = RegisterPayload::try_from_slice(&message.payload[..])
+                    .unwrap_or_else(|_| panic!("Invalid register payload format"));
+                self.internal_register_with_token(sender_id, payload.account_name, amount.into());
+
+                // Return 0 as the fee is collected and the rest goes to the new account
+                PromiseOrValue::Value(0.into())
+            }
             _ => panic!("Unsupported action"),
         }
     }
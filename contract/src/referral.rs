@@ -0,0 +1,386 @@
+use crate::role::Role;
+use crate::{Contract, ContractExt};
+use near_contract_standards::storage_management::StorageBalance;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, Promise};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralStats {
+    // Number of users referred by this user
+    pub referred_count: u64,
+
+    // Total reward earned from transfer fees of referred users
+    pub total_reward: U128,
+
+    // Reward that has not been claimed yet
+    pub available_reward: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Register or deposit storage balance with a referrer
+    #[payable]
+    pub fn storage_deposit_with_referrer(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+        referrer_id: AccountId,
+    ) -> StorageBalance {
+        let account_id = account_id.unwrap_or(env::predecessor_account_id());
+
+        // Referrer of a registered user can only be set by the user
+        require!(
+            account_id == env::predecessor_account_id()
+                || !self.user_accounts.contains_key(&account_id),
+            "Only the user can set their referrer"
+        );
+        let registration_only = registration_only.unwrap_or(false);
+        if registration_only && self.user_accounts.contains_key(&account_id) {
+            // Registered user pays for the referral from the deposit, the rest is refunded
+            self.internal_set_referrer(&account_id, referrer_id, env::attached_deposit());
+        } else {
+            // Referral is paid from the storage balance before any excess deposit is refunded
+            self.storage_deposit(Some(account_id.clone()), Some(false));
+            self.internal_set_referrer(&account_id, referrer_id, 0);
+            if registration_only {
+                self.internal_refund_registration_deposit(&account_id);
+            }
+        }
+
+        self.storage_balances.get(&account_id).unwrap()
+    }
+
+    // Claim referral reward into one of the referrer's accounts
+    #[payable]
+    pub fn claim_referral_reward(&mut self, account_name: String, amount: U128) {
+        assert_one_yocto();
+        let referrer_id = env::signer_account_id();
+
+        // Get account by account name
+        let mut account = self
+            .accounts
+            .get(&account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"));
+        require!(
+            account.owner_id == referrer_id,
+            "Unauthorized access to account"
+        );
+
+        // Subtract amount from available reward
+        let mut stats = self
+            .referral_stats
+            .get(&referrer_id)
+            .unwrap_or_else(|| panic!("The user {} has no referral reward", referrer_id));
+        stats.available_reward = Balance::from(stats.available_reward)
+            .checked_sub(amount.into())
+            .unwrap_or_else(|| panic!("Balance overflow"))
+            .into();
+        self.referral_stats.insert(&referrer_id, &stats);
+
+        // Add amount to account balance
+        account.balance = account
+            .balance
+            .checked_add(amount.into())
+            .unwrap_or_else(|| panic!("Balance overflow"));
+        self.accounts.insert(&account_name, &account);
+    }
+
+    // Set share of transfer fee paid to referrers
+    #[payable]
+    pub fn set_referral_fee(
+        &mut self,
+        referral_fee_numerator: U128,
+        referral_fee_denominator: U128,
+    ) {
+        assert_one_yocto();
+        self.internal_assert_role(Role::FeeManager);
+        require!(
+            referral_fee_denominator.0 > 0
+                && referral_fee_numerator.0 <= referral_fee_denominator.0,
+            "Invalid referral fee"
+        );
+
+        self.metadata.referral_fee_numerator = referral_fee_numerator;
+        self.metadata.referral_fee_denominator = referral_fee_denominator;
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get referrer of a user
+    pub fn get_referrer(&self, account_id: AccountId) -> Option<AccountId> {
+        self.referrers.get(&account_id)
+    }
+
+    // Get referral stats of a referrer
+    pub fn get_referral_stats(&self, account_id: AccountId) -> Option<ReferralStats> {
+        self.referral_stats.get(&account_id)
+    }
+}
+
+impl Contract {
+    // Record the referrer of a user, topping up their storage balance from the deposit
+    pub fn internal_set_referrer(
+        &mut self,
+        account_id: &AccountId,
+        referrer_id: AccountId,
+        deposit: Balance,
+    ) {
+        // Referral relationship cannot be changed once set
+        if self.referrers.contains_key(account_id) {
+            log!("The user {} already has a referrer", account_id);
+            if deposit > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(deposit);
+            }
+            return;
+        }
+
+        require!(&referrer_id != account_id, "Cannot refer yourself");
+        require!(
+            self.user_accounts.contains_key(&referrer_id),
+            format!("The referrer {} is not registered", referrer_id)
+        );
+
+        // Record referral relationship and update referrer stats
+        let initial_storage_usage = env::storage_usage();
+        let mut stats = self
+            .referral_stats
+            .get(&referrer_id)
+            .unwrap_or(ReferralStats {
+                referred_count: 0,
+                total_reward: 0.into(),
+                available_reward: 0.into(),
+            });
+        stats.referred_count += 1;
+        self.referral_stats.insert(&referrer_id, &stats);
+        self.referrers.insert(account_id, &referrer_id);
+
+        // Referred user pays for the referral storage
+        self.internal_charge_storage_with_deposit(account_id, initial_storage_usage, deposit);
+    }
+
+    // Credit referrer with a share of the transfer fee and return the share
+    pub fn internal_reward_referrer(
+        &mut self,
+        account_id: &AccountId,
+        transfer_fee: Balance,
+    ) -> Balance {
+        let referrer_id = match self.referrers.get(account_id) {
+            Some(referrer_id) => referrer_id,
+            None => return 0,
+        };
+        let mut stats = match self.referral_stats.get(&referrer_id) {
+            Some(stats) => stats,
+            None => return 0,
+        };
+
+        let reward = transfer_fee
+            .checked_mul(self.metadata.referral_fee_numerator.into())
+            .unwrap_or(0)
+            .checked_div(self.metadata.referral_fee_denominator.into())
+            .unwrap_or(0);
+        if reward == 0 {
+            return 0;
+        }
+
+        stats.total_reward = Balance::from(stats.total_reward)
+            .checked_add(reward)
+            .unwrap_or_else(|| panic!("Balance overflow"))
+            .into();
+        stats.available_reward = Balance::from(stats.available_reward)
+            .checked_add(reward)
+            .unwrap_or_else(|| panic!("Balance overflow"))
+            .into();
+        self.referral_stats.insert(&referrer_id, &stats);
+        reward
+    }
+
+    // Whether the user has referral reward left to claim, which unregistering would lose
+    pub fn internal_has_referral_reward(&self, account_id: &AccountId) -> bool {
+        self.referral_stats
+            .get(account_id)
+            .is_some_and(|stats| stats.available_reward.0 > 0)
+    }
+
+    pub fn internal_remove_referrer(&mut self, account_id: &AccountId) {
+        if let Some(referrer_id) = self.referrers.remove(account_id) {
+            if let Some(mut stats) = self.referral_stats.get(&referrer_id) {
+                stats.referred_count = stats.referred_count.saturating_sub(1);
+                self.referral_stats.insert(&referrer_id, &stats);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.set_referral_fee(1.into(), 2.into());
+
+        // accounts(3) refers accounts(4)
+        setup_user(&mut contract, &accounts(3), &[accounts(3).as_str()]);
+        setup_user(&mut contract, &accounts(4), &[]);
+        testing_env!(get_context(accounts(4))
+            .storage_usage(env::storage_usage())
+            .attached_deposit(
+                Balance::from(contract.metadata.account_storage_usage.0)
+                    * env::storage_byte_cost()
+                    * 2
+            )
+            .build());
+        contract.create_account(accounts(4).to_string(), Some(accounts(3)));
+        contract
+    }
+
+    #[test]
+    fn test_create_account_with_referrer() {
+        let contract = setup_contract();
+
+        assert_eq!(contract.get_referrer(accounts(4)), Some(accounts(3)));
+        assert_eq!(contract.get_referrer(accounts(3)), None);
+        assert_eq!(
+            contract.get_referral_stats(accounts(3)).unwrap(),
+            ReferralStats {
+                referred_count: 1,
+                total_reward: 0.into(),
+                available_reward: 0.into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_transfer_rewards_referrer() {
+        let mut contract = setup_contract();
+        contract.internal_deposit(accounts(4).to_string(), 1000.into());
+
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        contract.transfer(
+            accounts(4).to_string(),
+            accounts(3).to_string(),
+            1000.into(),
+        );
+
+        // Transfer fee of 10 is split between the referrer and the contract
+        let stats = contract.get_referral_stats(accounts(3)).unwrap();
+        assert_eq!(stats.total_reward, 5.into());
+        assert_eq!(stats.available_reward, 5.into());
+        assert_eq!(contract.total_transfer_fee, 5);
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.attached_deposit(1).build());
+        contract.claim_referral_reward(accounts(3).to_string(), 5.into());
+        assert_eq!(
+            contract.get_balance(accounts(3).to_string()).unwrap(),
+            995.into()
+        );
+        assert_eq!(
+            contract
+                .get_referral_stats(accounts(3))
+                .unwrap()
+                .available_reward,
+            0.into()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot unregister the user with unclaimed referral reward")]
+    fn test_unregister_with_unclaimed_referral_reward() {
+        let mut contract = setup_contract();
+        contract.internal_deposit(accounts(4).to_string(), 1000.into());
+
+        testing_env!(get_context(accounts(4)).build());
+        contract.transfer(
+            accounts(4).to_string(),
+            accounts(3).to_string(),
+            1000.into(),
+        );
+
+        testing_env!(get_context(accounts(3)).attached_deposit(1).build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "Balance overflow")]
+    fn test_claim_referral_reward_not_enough_reward() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.attached_deposit(1).build());
+        contract.claim_referral_reward(accounts(3).to_string(), 1.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot refer yourself")]
+    fn test_refer_yourself() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(5));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(
+                Balance::from(contract.metadata.user_storage_usage.0)
+                    * env::storage_byte_cost()
+                    * 2
+            )
+            .build());
+        contract.storage_deposit_with_referrer(None, None, accounts(5));
+    }
+
+    #[test]
+    fn test_register_only_with_referrer() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(5));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(
+                Balance::from(contract.metadata.user_storage_usage.0)
+                    * env::storage_byte_cost()
+                    * 2
+            )
+            .build());
+        let storage_balance = contract.storage_deposit_with_referrer(None, Some(true), accounts(3));
+
+        // Referral record is paid before the excess deposit is refunded
+        assert_eq!(contract.get_referrer(accounts(5)), Some(accounts(3)));
+        assert_eq!(storage_balance.available, 0.into());
+        assert_eq!(
+            contract
+                .get_referral_stats(accounts(3))
+                .unwrap()
+                .referred_count,
+            2
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the user can set their referrer")]
+    fn test_set_referrer_of_registered_user() {
+        let mut contract = setup_contract();
+
+        // accounts(4) cannot make itself the referrer of accounts(3)
+        let mut context = get_context(accounts(4));
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.storage_deposit_with_referrer(Some(accounts(3)), None, accounts(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_set_referral_fee_unauthorized_access() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_referral_fee(1.into(), 1.into());
+    }
+}
@@ -0,0 +1,196 @@
+use crate::event::emit_event;
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+    // Grants and revokes roles and holds every other role
+    Admin,
+
+    // Withdraws transfer fees and sets the referral fee
+    FeeManager,
+
+    // Pauses operations
+    Pauser,
+
+    // Sets the registration fee and manages the storage subsidy pool
+    TokenManager,
+
+    // Audits the contract, with no entry point of its own yet
+    Auditor,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Grant a role to an account
+    #[payable]
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        assert_one_yocto();
+        self.internal_assert_role(Role::Admin);
+
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        if !roles.contains(&role) {
+            roles.push(role.clone());
+            self.roles.insert(&account_id, &roles);
+        }
+        emit_event(
+            "grant_role",
+            json!({ "account_id": account_id, "role": role, "by": env::signer_account_id() }),
+        );
+    }
+
+    // Revoke a role from an account
+    #[payable]
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) {
+        assert_one_yocto();
+        self.internal_assert_role(Role::Admin);
+        self.internal_remove_role(&account_id, &role);
+        emit_event(
+            "revoke_role",
+            json!({ "account_id": account_id, "role": role, "by": env::signer_account_id() }),
+        );
+    }
+
+    // Give up a role held by the caller
+    #[payable]
+    pub fn renounce_role(&mut self, role: Role) {
+        assert_one_yocto();
+        let account_id = env::signer_account_id();
+        self.internal_remove_role(&account_id, &role);
+        emit_event(
+            "renounce_role",
+            json!({ "account_id": account_id, "role": role }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get roles granted to an account, where the contract owner is always an admin
+    pub fn get_roles(&self, account_id: AccountId) -> Vec<Role> {
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        if account_id == self.metadata.owner_id && !roles.contains(&Role::Admin) {
+            roles.insert(0, Role::Admin);
+        }
+        roles
+    }
+}
+
+impl Contract {
+    // Whether the account holds the role directly or through the admin role
+    pub fn internal_has_role(&self, account_id: &AccountId, role: Role) -> bool {
+        let roles = self.get_roles(account_id.clone());
+        roles.contains(&Role::Admin) || roles.contains(&role)
+    }
+
+    pub fn internal_assert_role(&self, role: Role) {
+        require!(
+            self.internal_has_role(&env::signer_account_id(), role),
+            "Unauthorized access"
+        );
+    }
+
+    fn internal_remove_role(&mut self, account_id: &AccountId, role: &Role) {
+        let mut roles = self.roles.get(account_id).unwrap_or_default();
+        require!(roles.contains(role), "Account does not have the role");
+        roles.retain(|r| r != role);
+        if roles.is_empty() {
+            self.roles.remove(account_id);
+        } else {
+            self.roles.insert(account_id, &roles);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pause::PauseFlags;
+    use crate::test::tests::{get_context, new_contract};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.grant_role(accounts(3), Role::Pauser);
+        contract
+    }
+
+    #[test]
+    fn test_grant_role() {
+        let contract = setup_contract();
+
+        assert_eq!(contract.get_roles(accounts(1)), vec![Role::Admin]);
+        assert_eq!(contract.get_roles(accounts(3)), vec![Role::Pauser]);
+        assert!(contract.get_roles(accounts(4)).is_empty());
+    }
+
+    #[test]
+    fn test_pause_by_pauser() {
+        let mut contract = setup_contract();
+
+        testing_env!(get_context(accounts(3)).attached_deposit(1).build());
+        contract.pause(PauseFlags {
+            deposits: true,
+            ..Default::default()
+        });
+        assert!(contract.get_paused().deposits);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_pause_after_renounce() {
+        let mut contract = setup_contract();
+
+        testing_env!(get_context(accounts(3)).attached_deposit(1).build());
+        contract.renounce_role(Role::Pauser);
+        contract.pause(PauseFlags {
+            deposits: true,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_withdraw_transfer_fee_after_revoke() {
+        let mut contract = setup_contract();
+        contract.grant_role(accounts(4), Role::FeeManager);
+        contract.revoke_role(accounts(4), Role::FeeManager);
+
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.withdraw_transfer_fee(0.into());
+    }
+
+    #[test]
+    fn test_set_registration_fee_by_token_manager() {
+        let mut contract = setup_contract();
+        contract.grant_role(accounts(4), Role::TokenManager);
+
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.set_registration_fee(10.into());
+        assert_eq!(contract.get_storage_subsidy().registration_fee, 10.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_set_registration_fee_by_fee_manager() {
+        let mut contract = setup_contract();
+        contract.grant_role(accounts(4), Role::FeeManager);
+
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.set_registration_fee(10.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_grant_role_by_non_admin() {
+        let mut contract = setup_contract();
+
+        testing_env!(get_context(accounts(3)).attached_deposit(1).build());
+        contract.grant_role(accounts(3), Role::Admin);
+    }
+}
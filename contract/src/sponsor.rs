@@ -0,0 +1,409 @@
+use crate::{Contract, ContractExt};
+use near_contract_standards::storage_management::StorageBalance;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Promise};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Sponsorship {
+    // Account ID that deposited storage balance for the user
+    pub sponsor_id: AccountId,
+
+    // Part of the storage balance deposited by the sponsor, returned to the
+    // sponsor when the user unregisters
+    pub amount: U128,
+
+    // Maximum number of accounts the user may own while sponsored
+    pub max_accounts: Option<u32>,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Deposit storage balance for another user, limiting the number of accounts it pays for
+    #[payable]
+    pub fn sponsor_storage_deposit(
+        &mut self,
+        account_id: AccountId,
+        max_accounts: Option<u32>,
+    ) -> StorageBalance {
+        let sponsor_id = env::predecessor_account_id();
+        require!(account_id != sponsor_id, "Cannot sponsor yourself");
+        require!(
+            !self.user_accounts.contains_key(&account_id)
+                || self.internal_is_sponsor(&account_id, &sponsor_id),
+            "Cannot sponsor a registered user"
+        );
+        self.storage_deposit(Some(account_id.clone()), None);
+        self.internal_set_sponsored_account_limit(&account_id, max_accounts);
+
+        self.storage_balances.get(&account_id).unwrap()
+    }
+
+    // Change the number of accounts a sponsored user may own
+    #[payable]
+    pub fn set_sponsored_account_limit(
+        &mut self,
+        account_id: AccountId,
+        max_accounts: Option<u32>,
+    ) {
+        assert_one_yocto();
+        self.internal_set_sponsored_account_limit(&account_id, max_accounts);
+    }
+
+    // Pay back the sponsor of the caller from the attached deposit, ending the
+    // sponsorship and its account limit
+    #[payable]
+    pub fn repay_sponsorship(&mut self) {
+        let account_id = env::predecessor_account_id();
+        let sponsorship = self
+            .sponsorships
+            .get(&account_id)
+            .unwrap_or_else(|| panic!("The user {} is not sponsored", account_id));
+        let amount = sponsorship.amount.0;
+        require!(
+            env::attached_deposit() >= amount,
+            format!("Attach {} yoctoNEAR to repay the sponsorship", amount)
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        self.sponsorships.remove(&account_id);
+        self.internal_refund_storage(&account_id, initial_storage_usage);
+
+        if amount > 0 {
+            Promise::new(sponsorship.sponsor_id).transfer(amount);
+        }
+        let refund = env::attached_deposit() - amount;
+        if refund > 0 {
+            Promise::new(account_id).transfer(refund);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get sponsor of a user and the storage balance they deposited
+    pub fn get_sponsorship(&self, account_id: AccountId) -> Option<Sponsorship> {
+        self.sponsorships.get(&account_id)
+    }
+}
+
+impl Contract {
+    // Record storage balance deposited by a sponsor, who pays for the record
+    // from the deposit
+    pub fn internal_add_sponsorship(
+        &mut self,
+        account_id: &AccountId,
+        sponsor_id: &AccountId,
+        amount: Balance,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        let mut sponsorship = self
+            .sponsorships
+            .get(account_id)
+            .unwrap_or_else(|| Sponsorship {
+                sponsor_id: sponsor_id.clone(),
+                amount: 0.into(),
+                max_accounts: None,
+            });
+        require!(
+            &sponsorship.sponsor_id == sponsor_id,
+            format!("The user {} is sponsored by another account", account_id)
+        );
+        sponsorship.amount = sponsorship
+            .amount
+            .0
+            .checked_add(amount)
+            .unwrap_or_else(|| panic!("Balance overflow"))
+            .into();
+        self.sponsorships.insert(account_id, &sponsorship);
+        self.internal_charge_storage(account_id, initial_storage_usage);
+    }
+
+    // Whether sponsor_id deposited storage balance for the user
+    pub fn internal_is_sponsor(&self, account_id: &AccountId, sponsor_id: &AccountId) -> bool {
+        self.sponsorships
+            .get(account_id)
+            .is_some_and(|sponsorship| &sponsorship.sponsor_id == sponsor_id)
+    }
+
+    fn internal_set_sponsored_account_limit(
+        &mut self,
+        account_id: &AccountId,
+        max_accounts: Option<u32>,
+    ) {
+        let mut sponsorship = self
+            .sponsorships
+            .get(account_id)
+            .unwrap_or_else(|| panic!("The user {} is not sponsored", account_id));
+        require!(
+            sponsorship.sponsor_id == env::predecessor_account_id(),
+            "Unauthorized access to sponsorship"
+        );
+        // Limit is stored in the sponsorship record paid from the user's storage balance
+        let initial_storage_usage = env::storage_usage();
+        sponsorship.max_accounts = max_accounts;
+        self.sponsorships.insert(account_id, &sponsorship);
+        if env::storage_usage() > initial_storage_usage {
+            self.internal_charge_storage(account_id, initial_storage_usage);
+        } else {
+            self.internal_refund_storage(account_id, initial_storage_usage);
+        }
+    }
+
+    // Sponsored user cannot take more accounts than the sponsor allows
+    pub fn internal_assert_sponsored_account_limit(&self, account_id: &AccountId) {
+        if let Some(max_accounts) = self
+            .sponsorships
+            .get(account_id)
+            .and_then(|sponsorship| sponsorship.max_accounts)
+        {
+            require!(
+                self.user_accounts.len(account_id) < max_accounts as u64,
+                "Sponsored account limit reached"
+            );
+        }
+    }
+
+    // Storage balance of a user that only a sponsor or the subsidy pool can get back
+    pub fn internal_locked_storage_balance(&self, account_id: &AccountId) -> Balance {
+        let sponsored = self
+            .sponsorships
+            .get(account_id)
+            .map_or(0, |sponsorship| sponsorship.amount.0);
+        sponsored + self.storage_subsidies.get(account_id).unwrap_or(0)
+    }
+
+    // Return the sponsored storage balance of an unregistered user to the sponsor
+    // and get the rest of the storage balance
+    pub fn internal_release_sponsorship(
+        &mut self,
+        account_id: &AccountId,
+        total: Balance,
+    ) -> Balance {
+        match self.sponsorships.remove(account_id) {
+            Some(sponsorship) => {
+                let amount = sponsorship.amount.0.min(total);
+                if amount > 0 {
+                    Promise::new(sponsorship.sponsor_id).transfer(amount);
+                }
+                total - amount
+            }
+            None => total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract};
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts};
+    use near_sdk::testing_env;
+
+    // Sponsor accounts(4) registers accounts(3) with room for two accounts
+    fn setup_contract(max_accounts: Option<u32>) -> (Contract, Balance) {
+        let mut contract = new_contract();
+        let deposit = Balance::from(
+            contract.metadata.user_storage_usage.0 + contract.metadata.account_storage_usage.0 * 2,
+        ) * env::storage_byte_cost();
+
+        set_deposit(accounts(4), deposit);
+        contract.sponsor_storage_deposit(accounts(3), max_accounts);
+        (contract, deposit)
+    }
+
+    // accounts(3) registers with room for one account
+    fn setup_registered_user() -> (Contract, Balance) {
+        let mut contract = new_contract();
+        let deposit = Balance::from(
+            contract.metadata.user_storage_usage.0 + contract.metadata.account_storage_usage.0,
+        ) * env::storage_byte_cost();
+
+        set_deposit(accounts(3), deposit);
+        contract.storage_deposit(None, None);
+        (contract, deposit)
+    }
+
+    fn set_deposit(account_id: AccountId, deposit: Balance) {
+        let mut context = get_context(account_id);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .build());
+    }
+
+    fn create_account(contract: &mut Contract, account_name: &str) {
+        let mut context = get_context(accounts(3));
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.create_account(account_name.to_owned(), None);
+    }
+
+    // NEAR transfers created by the last call
+    fn get_transfers() -> Vec<(AccountId, Balance)> {
+        get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        VmAction::Transfer { deposit } => Some((receiver_id.clone(), deposit)),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sponsor_storage_deposit() {
+        let (contract, deposit) = setup_contract(Some(1));
+
+        assert_eq!(
+            contract.get_sponsorship(accounts(3)),
+            Some(Sponsorship {
+                sponsor_id: accounts(4),
+                amount: deposit.into(),
+                max_accounts: Some(1),
+            })
+        );
+        assert_eq!(
+            contract.storage_balance_of(accounts(3)).unwrap().total,
+            deposit.into()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Sponsored account limit reached")]
+    fn test_sponsored_account_limit() {
+        let (mut contract, _) = setup_contract(Some(1));
+        create_account(&mut contract, "first");
+        create_account(&mut contract, "second");
+    }
+
+    #[test]
+    fn test_raise_sponsored_account_limit() {
+        let (mut contract, _) = setup_contract(Some(1));
+        create_account(&mut contract, "first");
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_sponsored_account_limit(accounts(3), None);
+        create_account(&mut contract, "second");
+        assert_eq!(
+            contract
+                .get_accounts(accounts(3), None, None)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access to sponsorship")]
+    fn test_set_sponsored_account_limit_by_user() {
+        let (mut contract, _) = setup_contract(Some(1));
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_sponsored_account_limit(accounts(3), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot withdraw sponsored storage balance")]
+    fn test_withdraw_sponsored_storage_balance() {
+        let (mut contract, _) = setup_contract(None);
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.attached_deposit(1).build());
+        contract.storage_withdraw(Some(1.into()));
+    }
+
+    #[test]
+    fn test_deposit_for_registered_user_is_a_gift() {
+        let (mut contract, deposit) = setup_registered_user();
+
+        // Depositing for a registered user does not make the caller a sponsor
+        set_deposit(accounts(5), 1);
+        contract.storage_deposit(Some(accounts(3)), None);
+        assert!(contract.get_sponsorship(accounts(3)).is_none());
+        assert_eq!(
+            contract.storage_balance_of(accounts(3)).unwrap().total,
+            (deposit + 1).into()
+        );
+        create_account(&mut contract, "first");
+    }
+
+    #[test]
+    #[should_panic(expected = "The user danny is not sponsored")]
+    fn test_set_account_limit_of_registered_user() {
+        let (mut contract, _) = setup_registered_user();
+
+        set_deposit(accounts(5), 1);
+        contract.storage_deposit(Some(accounts(3)), None);
+        testing_env!(get_context(accounts(5)).attached_deposit(1).build());
+        contract.set_sponsored_account_limit(accounts(3), Some(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot sponsor a registered user")]
+    fn test_sponsor_registered_user() {
+        let (mut contract, deposit) = setup_registered_user();
+
+        set_deposit(accounts(5), deposit);
+        contract.sponsor_storage_deposit(accounts(3), Some(0));
+    }
+
+    #[test]
+    fn test_repay_sponsorship() {
+        let (mut contract, deposit) = setup_contract(Some(0));
+
+        set_deposit(accounts(3), deposit + 10);
+        contract.repay_sponsorship();
+        assert!(contract.get_sponsorship(accounts(3)).is_none());
+        assert_eq!(
+            get_transfers(),
+            vec![(accounts(4), deposit), (accounts(3), 10)]
+        );
+
+        // Account limit no longer applies
+        create_account(&mut contract, "first");
+    }
+
+    #[test]
+    #[should_panic(expected = "to repay the sponsorship")]
+    fn test_repay_sponsorship_insufficient_deposit() {
+        let (mut contract, deposit) = setup_contract(None);
+
+        set_deposit(accounts(3), deposit - 1);
+        contract.repay_sponsorship();
+    }
+
+    #[test]
+    fn test_unregister_refunds_sponsor() {
+        let (mut contract, deposit) = setup_contract(None);
+        create_account(&mut contract, "first");
+
+        // User's own deposit is refunded to the user
+        let mut context = get_context(accounts(3));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(10)
+            .build());
+        contract.storage_deposit(None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        assert!(contract.storage_unregister(Some(true)));
+
+        assert_eq!(
+            get_transfers(),
+            vec![(accounts(4), deposit), (accounts(3), 11)]
+        );
+        assert!(contract.get_sponsorship(accounts(3)).is_none());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, Promise};
+
+// Tip in yoctoNEAR paid to the keeper for each execution (0.001 NEAR)
+pub const KEEPER_TIP: Balance = 1_000_000_000_000_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StandingOrder {
+    pub id: U64,
+
+    // Owner who registered the order and prepaid its storage and keeper tips
+    pub owner_id: AccountId,
+
+    pub sender_account_name: String,
+
+    pub receiver_account_name: String,
+
+    // Amount transferred on each execution
+    pub amount: U128,
+
+    // Duration in nanoseconds between executions
+    pub interval: U64,
+
+    // Number of executions left
+    pub remaining_count: u32,
+
+    // Timestamp in nanoseconds when the order is next due
+    pub next_execution_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Register a recurring transfer, attaching a keeper tip for each execution
+    #[payable]
+    pub fn create_standing_order(
+        &mut self,
+        sender_account_name: String,
+        receiver_account_name: String,
+        amount: U128,
+        interval: U64,
+        count: u32,
+        start_at: Option<U64>,
+    ) -> U64 {
+        require!(!self.paused.transfers, "Transfers are paused");
+        require!(amount.0 > 0, "Order amount must be positive");
+        require!(interval.0 > 0, "Order interval must be positive");
+        require!(count > 0, "Order count must be positive");
+        let keeper_tips = KEEPER_TIP * Balance::from(count);
+        require!(
+            env::attached_deposit() >= keeper_tips,
+            "Attached deposit must cover keeper tips"
+        );
+
+        let owner_id = env::signer_account_id();
+        let sender_account = self
+            .accounts
+            .get(&sender_account_name)
+            .unwrap_or_else(|| panic!("Sender account does not exist"));
+        require!(
+            sender_account.owner_id == owner_id,
+            "Unauthorized access to account"
+        );
+        require!(
+            sender_account.multisig.is_none(),
+            "Multi-signature account requires a proposal"
+        );
+        require!(
+            self.accounts.get(&receiver_account_name).is_some(),
+            "Receiver account does not exist"
+        );
+        require!(
+            sender_account_name != receiver_account_name,
+            "Cannot transfer to the same account"
+        );
+
+        // Owner pays for the order storage
+        let initial_storage_usage = env::storage_usage();
+        let id = self.next_standing_order_id;
+        self.next_standing_order_id += 1;
+        let order = StandingOrder {
+            id: id.into(),
+            owner_id,
+            sender_account_name,
+            receiver_account_name,
+            amount,
+            interval,
+            remaining_count: count,
+            next_execution_at: start_at.unwrap_or_else(|| env::block_timestamp().into()),
+        };
+        self.standing_orders.insert(&id, &order);
+        self.internal_add_open_transfer(&order.sender_account_name, &order.receiver_account_name);
+        self.internal_charge_storage_with_deposit(
+            &order.owner_id,
+            initial_storage_usage,
+            env::attached_deposit() - keeper_tips,
+        );
+        id.into()
+    }
+
+    // Cancel a standing order and refund the unused keeper tips
+    #[payable]
+    pub fn cancel_standing_order(&mut self, order_id: U64) {
+        assert_one_yocto();
+        let order = self
+            .standing_orders
+            .get(&order_id.0)
+            .unwrap_or_else(|| panic!("Standing order does not exist"));
+        require!(
+            order.owner_id == env::signer_account_id(),
+            "Unauthorized access to standing order"
+        );
+
+        self.internal_remove_standing_order(&order);
+        Promise::new(order.owner_id).transfer(KEEPER_TIP * Balance::from(order.remaining_count));
+    }
+
+    // Check up to limit standing orders, continuing where the previous call
+    // stopped, execute the due ones and pay the caller a tip for each
+    // successful execution. Return the number of executed orders.
+    pub fn execute_due_orders(&mut self, limit: u32) -> u32 {
+        let now = env::block_timestamp();
+        let mut executed_count = 0;
+        let limit = limit.min(self.standing_orders.len() as u32);
+        for _ in 0..limit {
+            if self.standing_order_cursor >= self.standing_orders.len() {
+                self.standing_order_cursor = 0;
+            }
+            let mut order = self
+                .standing_orders
+                .values_as_vector()
+                .get(self.standing_order_cursor)
+                .unwrap();
+            if order.next_execution_at.0 > now {
+                self.standing_order_cursor += 1;
+                continue;
+            }
+
+            // A failed execution stays due for a later call and is not tipped,
+            // so it does not block the queue
+            if let Err(err) = self.internal_execute_standing_order(&order) {
+                log!("Standing order {} failed: {}", order.id.0, err);
+                self.standing_order_cursor += 1;
+                continue;
+            }
+
+            executed_count += 1;
+            order.remaining_count -= 1;
+            if order.remaining_count == 0 {
+                // Removal moves the last order to the cursor, so the cursor stays
+                self.internal_remove_standing_order(&order);
+            } else {
+                order.next_execution_at = (order.next_execution_at.0 + order.interval.0).into();
+                self.standing_orders.insert(&order.id.0, &order);
+                self.standing_order_cursor += 1;
+            }
+        }
+
+        if executed_count > 0 {
+            Promise::new(env::predecessor_account_id())
+                .transfer(KEEPER_TIP * Balance::from(executed_count));
+        }
+        executed_count
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get standing order by ID
+    pub fn get_standing_order(&self, order_id: U64) -> Option<StandingOrder> {
+        self.standing_orders.get(&order_id.0)
+    }
+}
+
+impl Contract {
+    // Transfer the order amount with the same checks and fees as transfer
+    fn internal_execute_standing_order(
+        &mut self,
+        order: &StandingOrder,
+    ) -> Result<(), &'static str> {
+        let (sender_account, receiver_account, transfer_fee) = self.internal_prepare_transfer(
+            &order.sender_account_name,
+            &order.receiver_account_name,
+            order.amount.into(),
+            Some(&order.owner_id),
+        )?;
+        self.internal_try_before_outflow(
+            &order.sender_account_name,
+            &sender_account,
+            order.amount.into(),
+        )?;
+        self.internal_collect_transfer_fee(&order.owner_id, transfer_fee);
+
+        // Update accounts in storage
+        self.accounts
+            .insert(&order.sender_account_name, &sender_account);
+        self.accounts
+            .insert(&order.receiver_account_name, &receiver_account);
+        Ok(())
+    }
+
+    // Remove standing order and refund its storage to the owner
+    fn internal_remove_standing_order(&mut self, order: &StandingOrder) {
+        let initial_storage_usage = env::storage_usage();
+        self.standing_orders.remove(&order.id.0);
+        self.internal_remove_open_transfer(
+            &order.sender_account_name,
+            &order.receiver_account_name,
+        );
+        self.internal_refund_storage(&order.owner_id, initial_storage_usage);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{
+        test_utils::{accounts, get_created_receipts},
+        testing_env,
+    };
+
+    const INTERVAL: u64 = 1_000;
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        for account_id in [accounts(1), accounts(3)] {
+            setup_user(&mut contract, &account_id, &[account_id.as_str()]);
+        }
+        contract.internal_deposit(accounts(1).to_string(), 250.into());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(KEEPER_TIP * 3).build());
+        contract.create_standing_order(
+            accounts(1).to_string(),
+            accounts(3).to_string(),
+            100.into(),
+            INTERVAL.into(),
+            3,
+            None,
+        );
+        contract
+    }
+
+    #[test]
+    fn test_execute_due_orders() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        assert_eq!(contract.execute_due_orders(10), 1);
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            150.into()
+        );
+        assert_eq!(
+            contract.get_balance(accounts(3).to_string()).unwrap(),
+            99.into()
+        );
+        assert_eq!(contract.total_transfer_fee, 1);
+
+        // Order is not due again until the interval has passed
+        assert_eq!(contract.execute_due_orders(10), 0);
+        assert_eq!(
+            contract
+                .get_standing_order(0.into())
+                .unwrap()
+                .remaining_count,
+            2
+        );
+    }
+
+    #[test]
+    fn test_execute_due_orders_insufficient_balance() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(4));
+        for i in 0..2 {
+            testing_env!(context.block_timestamp(INTERVAL * i).build());
+            assert_eq!(contract.execute_due_orders(10), 1);
+        }
+
+        // Failed execution is not tipped and the order stays due
+        testing_env!(context.block_timestamp(INTERVAL * 2).build());
+        assert_eq!(contract.execute_due_orders(10), 0);
+        assert!(get_created_receipts().is_empty());
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            50.into()
+        );
+        let order = contract.get_standing_order(0.into()).unwrap();
+        assert_eq!(order.remaining_count, 1);
+        assert_eq!(order.next_execution_at, (INTERVAL * 2).into());
+
+        // Order runs once the sender is funded again
+        contract.internal_deposit(accounts(1).to_string(), 50.into());
+        testing_env!(context.block_timestamp(INTERVAL * 3).build());
+        assert_eq!(contract.execute_due_orders(10), 1);
+        assert!(contract.get_standing_order(0.into()).is_none());
+    }
+
+    #[test]
+    fn test_execute_due_orders_resumes_at_cursor() {
+        let mut contract = setup_contract();
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(KEEPER_TIP).build());
+        contract.create_standing_order(
+            accounts(1).to_string(),
+            accounts(3).to_string(),
+            10.into(),
+            INTERVAL.into(),
+            1,
+            None,
+        );
+
+        // Each call checks one order and the next call continues after it
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        assert_eq!(contract.execute_due_orders(1), 1);
+        assert_eq!(contract.execute_due_orders(1), 1);
+        assert!(contract.get_standing_order(1.into()).is_none());
+        assert_eq!(
+            contract
+                .get_standing_order(0.into())
+                .unwrap()
+                .remaining_count,
+            2
+        );
+    }
+
+    #[test]
+    fn test_cancel_standing_order() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.cancel_standing_order(0.into());
+        assert!(contract.get_standing_order(0.into()).is_none());
+
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        assert_eq!(contract.execute_due_orders(10), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must cover keeper tips")]
+    fn test_create_standing_order_without_tips() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(KEEPER_TIP).build());
+        contract.create_standing_order(
+            accounts(1).to_string(),
+            accounts(3).to_string(),
+            100.into(),
+            INTERVAL.into(),
+            2,
+            None,
+        );
+    }
+}
@@ -1,20 +1,37 @@
 use crate::{Account, Contract, ContractExt};
-use near_contract_standards::storage_management::{
-    StorageBalance, StorageBalanceBounds, StorageManagement,
+use near_contract_standards::storage_management::{StorageBalance, StorageBalanceBounds};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, Promise, StorageUsage,
 };
-use near_sdk::json_types::U128;
-use near_sdk::{assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, Promise};
 
+// Maximum number of accounts removed by a single call
+pub const MAX_ACCOUNTS_PER_UNREGISTER: u64 = 100;
+
+// Storage balance of a user as defined by NEP-145, with the user's last activity
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceView {
+    pub total: U128,
+    pub available: U128,
+
+    // Timestamp in nanoseconds of the user's last storage or account creation activity
+    pub last_activity_at: Option<U64>,
+}
+
+// Storage management of NEP-145, with storage_balance_of extended by the last activity
 #[near_bindgen]
-impl StorageManagement for Contract {
+impl Contract {
     #[payable]
-    fn storage_deposit(
+    pub fn storage_deposit(
         &mut self,
         account_id: Option<AccountId>,
         registration_only: Option<bool>,
     ) -> StorageBalance {
         let amount = env::attached_deposit();
-        let account_id = account_id.unwrap_or(env::predecessor_account_id());
+        let sponsor_id = env::predecessor_account_id();
+        let account_id = account_id.unwrap_or(sponsor_id.clone());
         let registration_only = registration_only.unwrap_or(false);
 
         // Check if user has registered their account
@@ -40,6 +57,13 @@ impl StorageManagement for Contract {
                     .unwrap_or_else(|| panic!("Balance overflow"))
                     .into();
                 self.storage_balances.insert(&account_id, &storage_balance);
+                self.internal_touch_user(&account_id);
+
+                // Deposit from the sponsor of the user is returned to the sponsor,
+                // deposits from anyone else are gifts
+                if sponsor_id != account_id && self.internal_is_sponsor(&account_id, &sponsor_id) {
+                    self.internal_add_sponsorship(&account_id, &sponsor_id, amount);
+                }
             }
         } else {
             // Attached deposit must be enough to create an account
@@ -49,31 +73,27 @@ impl StorageManagement for Contract {
                 "Insufficient deposit to register a user"
             );
 
-            // Create a new user entry with an empty list of associated accounts
-            self.user_accounts.insert(&account_id, &vec![]);
-            if registration_only {
-                // Refund excess deposit on registration only
-                let refund = amount - min_balance;
-                if refund > 0 {
-                    Promise::new(env::predecessor_account_id()).transfer(refund);
-                }
-                self.storage_balances.insert(
-                    &account_id,
-                    &StorageBalance {
-                        total: min_balance.into(),
-                        available: 0.into(),
-                    },
-                );
-            } else {
-                // Add deposit to the storage balance
-                let storage_balance = StorageBalance {
+            // Create a new user entry with an empty list of associated accounts,
+            // charging the storage it actually uses to the deposit
+            let initial_storage_usage = env::storage_usage();
+            self.user_accounts.insert_user(&account_id);
+            self.user_activity
+                .insert(&account_id, &env::block_timestamp());
+            self.storage_balances.insert(
+                &account_id,
+                &StorageBalance {
                     total: amount.into(),
-                    available: Balance::from(amount)
-                        .checked_sub(min_balance)
-                        .unwrap_or_else(|| panic!("Balance overflow"))
-                        .into(),
-                };
-                self.storage_balances.insert(&account_id, &storage_balance);
+                    available: amount.into(),
+                },
+            );
+            self.internal_charge_storage(&account_id, initial_storage_usage);
+
+            // Deposit for another user is returned to the sponsor
+            if sponsor_id != account_id {
+                self.internal_add_sponsorship(&account_id, &sponsor_id, amount);
+            }
+            if registration_only {
+                self.internal_refund_registration_deposit(&account_id);
             }
         }
 
@@ -81,7 +101,7 @@ impl StorageManagement for Contract {
     }
 
     #[payable]
-    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
         assert_one_yocto();
         let predecessor_account_id = env::predecessor_account_id();
 
@@ -90,6 +110,8 @@ impl StorageManagement for Contract {
             .storage_balances
             .get(&predecessor_account_id)
             .unwrap_or_else(|| panic!("The user {} is not registered", predecessor_account_id));
+        let locked_balance = self.internal_locked_storage_balance(&predecessor_account_id);
+        self.internal_touch_user(&predecessor_account_id);
         match amount {
             Some(amount) => {
                 // Refund the requested amount
@@ -101,6 +123,10 @@ impl StorageManagement for Contract {
                     .checked_sub(amount.into())
                     .unwrap_or_else(|| panic!("Balance overflow"))
                     .into();
+                require!(
+                    storage_balance.total.0 >= locked_balance,
+                    "Cannot withdraw sponsored storage balance"
+                );
                 if amount > 0.into() {
                     Promise::new(predecessor_account_id.clone()).transfer(amount.0 + 1);
                 }
@@ -109,16 +135,19 @@ impl StorageManagement for Contract {
                 storage_balance
             }
             None => {
-                // Refund the entire available balance
-                if storage_balance.available > 0.into() {
-                    Promise::new(predecessor_account_id.clone())
-                        .transfer(storage_balance.available.0 + 1);
+                // Refund the entire available balance not deposited by a sponsor
+                let amount = storage_balance
+                    .available
+                    .0
+                    .min(storage_balance.total.0.saturating_sub(locked_balance));
+                if amount > 0 {
+                    Promise::new(predecessor_account_id.clone()).transfer(amount + 1);
                 }
                 storage_balance.total = Balance::from(storage_balance.total)
-                    .checked_sub(storage_balance.available.into())
+                    .checked_sub(amount)
                     .unwrap_or_else(|| panic!("Balance overflow"))
                     .into();
-                storage_balance.available = 0.into();
+                storage_balance.available = (storage_balance.available.0 - amount).into();
                 self.storage_balances
                     .insert(&predecessor_account_id, &storage_balance);
                 storage_balance
@@ -127,39 +156,37 @@ impl StorageManagement for Contract {
     }
 
     #[payable]
-    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+    pub fn storage_unregister(&mut self, force: Option<bool>) -> bool {
         assert_one_yocto();
         let account_id = env::predecessor_account_id();
         let force = force.unwrap_or(false);
 
         // Check if the user is registered
-        if let Some(accounts) = self.user_accounts.get(&account_id) {
-            let storage_balance = self.storage_balances.get(&account_id).unwrap();
-            if accounts.is_empty() || force {
-                // Remove user
-                self.user_accounts.remove(&account_id);
-
-                // Remove all associated accounts
-                for account in accounts.iter() {
-                    self.accounts.remove(&account);
-                }
-
-                // Remove storage balance
-                self.storage_balances.remove(&account_id);
-
-                // Refund entire deposit
-                Promise::new(account_id.clone()).transfer(storage_balance.total.0 + 1);
-                true
-            } else {
+        if self.user_accounts.contains_key(&account_id) {
+            require!(
+                !self.internal_has_referral_reward(&account_id),
+                "Cannot unregister the user with unclaimed referral reward"
+            );
+            if self.user_accounts.len(&account_id) > 0 && !force {
                 panic!("Cannot unregister the user with associated accounts");
             }
+
+            // Users with more accounts than one call can remove shrink them first
+            require!(
+                self.user_accounts.len(&account_id) <= MAX_ACCOUNTS_PER_UNREGISTER,
+                "Too many accounts to unregister, remove them with remove_user_accounts first"
+            );
+            self.internal_remove_user_accounts(&account_id, MAX_ACCOUNTS_PER_UNREGISTER);
+            let refund = self.internal_unregister(&account_id);
+            Promise::new(account_id.clone()).transfer(refund + 1);
+            true
         } else {
             log!("The user {} is not registered", account_id);
             false
         }
     }
 
-    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
         let required_storage_balance =
             Balance::from(self.metadata.user_storage_usage.0) * env::storage_byte_cost();
         StorageBalanceBounds {
@@ -168,32 +195,44 @@ impl StorageManagement for Contract {
         }
     }
 
-    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
-        self.storage_balances.get(&account_id)
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalanceView> {
+        self.storage_balances
+            .get(&account_id)
+            .map(|storage_balance| StorageBalanceView {
+                total: storage_balance.total,
+                available: storage_balance.available,
+                last_activity_at: self.get_user_activity(account_id),
+            })
     }
 }
 
+#[near_bindgen]
 impl Contract {
-    pub fn internal_create_account(&mut self, account_id: AccountId, account_name: String) {
-        // Retrieve storage balance of the account owner
-        let mut storage_balance = self
-            .storage_balances
-            .get(&account_id)
-            .unwrap_or_else(|| panic!("The user {} is not registered", account_id));
-        let amount =
-            Balance::from(self.metadata.account_storage_usage.0) * env::storage_byte_cost();
-
+    // Remove up to limit accounts of the caller with their balances, as forced
+    // unregistering does, and return the number of removed accounts
+    #[payable]
+    pub fn remove_user_accounts(&mut self, limit: u64) -> u64 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
         require!(
-            storage_balance.available.0 >= amount,
-            "Insufficient deposit to create an account"
+            self.user_accounts.contains_key(&account_id),
+            format!("The user {} is not registered", account_id)
         );
+        self.internal_touch_user(&account_id);
 
-        // Subtract the account storage cost from the available balance
-        storage_balance.available = Balance::from(storage_balance.available)
-            .checked_sub(amount)
-            .unwrap_or_else(|| panic!("Balance overflow"))
-            .into();
-        self.storage_balances.insert(&account_id, &storage_balance);
+        let initial_storage_usage = env::storage_usage();
+        let accounts =
+            self.internal_remove_user_accounts(&account_id, limit.min(MAX_ACCOUNTS_PER_UNREGISTER));
+        self.internal_refund_storage(&account_id, initial_storage_usage);
+        accounts.len() as u64
+    }
+}
+
+impl Contract {
+    pub fn internal_create_account(&mut self, account_id: AccountId, account_name: String) {
+        self.internal_assert_sponsored_account_limit(&account_id);
+        self.internal_touch_user(&account_id);
+        let initial_storage_usage = env::storage_usage();
 
         // Create new empty account
         self.accounts.insert(
@@ -201,14 +240,208 @@ impl Contract {
             &(Account {
                 owner_id: account_id.clone(),
                 balance: 0,
+                multisig: None,
+                unlock_at: None,
+                last_activity_at: 0,
             }),
         );
 
         // Add account to user's list of accounts
-        let mut user_account = self.user_accounts.get(&account_id).unwrap();
-        user_account.push(account_name.clone());
-        self.user_accounts.insert(&account_id, &user_account);
+        self.user_accounts
+            .insert_account(&account_id, &account_name);
+
+        // Charge the storage actually used by the account to its owner
+        self.internal_charge_storage(&account_id, initial_storage_usage);
+    }
+
+    // Refund the deposit a new user does not need on registration only
+    pub fn internal_refund_registration_deposit(&mut self, account_id: &AccountId) {
+        let mut storage_balance = self.storage_balances.get(account_id).unwrap();
+        let refund = storage_balance.available.0;
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        storage_balance.total = (storage_balance.total.0 - refund).into();
+        storage_balance.available = 0.into();
+        self.storage_balances.insert(account_id, &storage_balance);
+        if let Some(mut sponsorship) = self.sponsorships.get(account_id) {
+            sponsorship.amount = storage_balance.total;
+            self.sponsorships.insert(account_id, &sponsorship);
+        }
+    }
+
+    // Remove up to limit accounts of a user with their records and return their names
+    pub fn internal_remove_user_accounts(
+        &mut self,
+        account_id: &AccountId,
+        limit: u64,
+    ) -> Vec<String> {
+        let accounts = self.user_accounts.remove_accounts(account_id, limit);
+        for account in accounts.iter() {
+            require!(
+                !self.internal_has_open_transfers(account),
+                "Cannot unregister the user with open escrows or transfers"
+            );
+            require!(
+                !self.internal_has_pending_withdrawals(account),
+                "Cannot unregister the user with pending withdrawals"
+            );
+            self.accounts.remove(account);
+            self.internal_remove_account_proposals(account);
+            self.allowances.remove(account);
+            self.spending_limits.remove(account);
+            self.account_escrows.remove(account);
+            self.account_open_transfers.remove(account);
+            self.guardians.remove(account);
+        }
+        accounts
+    }
+
+    // Unregister a user whose accounts have all been removed
+    pub fn internal_unregister(&mut self, account_id: &AccountId) -> Balance {
+        let storage_balance = self.storage_balances.get(account_id).unwrap();
+
+        // Remove user
+        self.user_accounts.remove(account_id);
+        self.user_activity.remove(account_id);
+
+        // Remove referral records
+        self.internal_remove_referrer(account_id);
+        self.referral_stats.remove(account_id);
+
+        // Remove storage balance
+        self.storage_balances.remove(account_id);
+
+        // Refund entire deposit except what the subsidy pool paid,
+        // returning what a sponsor paid to the sponsor
+        let refund = self.internal_release_subsidy(account_id, storage_balance.total.0);
+        self.internal_release_sponsorship(account_id, refund)
+    }
+
+    // Move an account to a new owner, who takes over its storage cost
+    pub fn internal_transfer_account(
+        &mut self,
+        account_name: &String,
+        new_owner_id: &AccountId,
+        deposit: Balance,
+    ) {
+        self.internal_assert_sponsored_account_limit(new_owner_id);
+        let mut account = self.accounts.get(account_name).unwrap();
+        let old_owner_id = account.owner_id.clone();
+
+        // Refund the previous owner if still registered
+        let initial_storage_usage = env::storage_usage();
+        self.accounts.remove(account_name);
+        if self.user_accounts.contains_key(&old_owner_id) {
+            self.user_accounts
+                .remove_account(&old_owner_id, account_name);
+        }
+        self.internal_refund_storage(&old_owner_id, initial_storage_usage);
+
+        // Charge the new owner for the account storage
+        let initial_storage_usage = env::storage_usage();
+        account.owner_id = new_owner_id.clone();
+        self.accounts.insert(account_name, &account);
+        self.user_accounts
+            .insert_account(new_owner_id, account_name);
+        self.internal_charge_storage_with_deposit(new_owner_id, initial_storage_usage, deposit);
+    }
+
+    // Charge storage used since initial_storage_usage to the user's storage balance
+    pub fn internal_charge_storage(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage_usage: StorageUsage,
+    ) {
+        self.internal_charge_storage_with_deposit(account_id, initial_storage_usage, 0);
+    }
+
+    // Charge storage used since initial_storage_usage to the user's storage balance,
+    // topping it up from the attached deposit and refunding the unused deposit to the caller
+    pub fn internal_charge_storage_with_deposit(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage_usage: StorageUsage,
+        deposit: Balance,
+    ) {
+        let mut storage_balance = self
+            .storage_balances
+            .get(account_id)
+            .unwrap_or_else(|| panic!("The user {} is not registered", account_id));
+        let amount = Balance::from(env::storage_usage().saturating_sub(initial_storage_usage))
+            * env::storage_byte_cost();
+
+        // Top up the storage balance with as much of the deposit as is missing
+        let top_up = amount.saturating_sub(storage_balance.available.0);
+        if deposit < top_up {
+            panic!(
+                "Insufficient storage deposit, attach {} more yoctoNEAR",
+                top_up - deposit
+            );
+        }
+
+        storage_balance.total = Balance::from(storage_balance.total)
+            .checked_add(top_up)
+            .unwrap_or_else(|| panic!("Balance overflow"))
+            .into();
+        storage_balance.available =
+            (Balance::from(storage_balance.available) + top_up - amount).into();
+        self.storage_balances.insert(account_id, &storage_balance);
+
+        // Refund the rest of the deposit
+        let refund = deposit - top_up;
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+
+    // Refund as much of a deposit added to the storage balance as is still available
+    pub fn internal_refund_unused_deposit(&mut self, account_id: &AccountId, deposit: Balance) {
+        let mut storage_balance = self.storage_balances.get(account_id).unwrap();
+        let refund = deposit.min(storage_balance.available.0);
+        if refund > 0 {
+            storage_balance.total = (storage_balance.total.0 - refund).into();
+            storage_balance.available = (storage_balance.available.0 - refund).into();
+            self.storage_balances.insert(account_id, &storage_balance);
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+
+            // Deposit refunded to the sponsor no longer counts as sponsored
+            if let Some(mut sponsorship) = self
+                .sponsorships
+                .get(account_id)
+                .filter(|sponsorship| sponsorship.sponsor_id == env::predecessor_account_id())
+            {
+                sponsorship.amount = sponsorship.amount.0.saturating_sub(refund).into();
+                self.sponsorships.insert(account_id, &sponsorship);
+            }
+        }
     }
+
+    // Refund storage released since initial_storage_usage to the user's storage balance
+    pub fn internal_refund_storage(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage_usage: StorageUsage,
+    ) {
+        // User may have unregistered in the meantime
+        if let Some(mut storage_balance) = self.storage_balances.get(account_id) {
+            let amount = Balance::from(initial_storage_usage.saturating_sub(env::storage_usage()))
+                * env::storage_byte_cost();
+            storage_balance.available = Balance::from(storage_balance.available)
+                .checked_add(amount)
+                .unwrap_or_else(|| panic!("Balance overflow"))
+                .into();
+            self.storage_balances.insert(account_id, &storage_balance);
+        }
+    }
+}
+
+// Require a full access key while accepting extra deposit for storage
+pub fn assert_at_least_one_yocto() {
+    require!(
+        env::attached_deposit() >= 1,
+        "Requires attached deposit of at least 1 yoctoNEAR"
+    );
 }
 
 #[cfg(test)]
@@ -235,6 +468,7 @@ mod test {
         let mut context = get_context(accounts(1));
         testing_env!(context.build());
         let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+        let initial_storage_usage = env::storage_usage();
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -244,6 +478,7 @@ mod test {
             .predecessor_account_id(accounts(1))
             .build());
         contract.storage_deposit(None, Some(true));
+        let user_storage_usage = env::storage_usage() - initial_storage_usage;
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -252,14 +487,97 @@ mod test {
             .build());
         contract.storage_deposit(None, None);
 
+        // Only the storage actually used by the user is kept
         let storage_balance = contract.storage_balance_of(accounts(1)).unwrap();
+        assert!(user_storage_usage < contract.metadata.user_storage_usage.0);
         assert_eq!(
             Balance::from(storage_balance.total),
-            Balance::from(contract.metadata.user_storage_usage.0) * env::storage_byte_cost() + 1
+            Balance::from(user_storage_usage) * env::storage_byte_cost() + 1
         );
         assert_eq!(Balance::from(storage_balance.available), 1)
     }
 
+    #[test]
+    fn test_create_account_charges_actual_storage() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+        let deposit = Balance::from(
+            contract.metadata.user_storage_usage.0 + contract.metadata.account_storage_usage.0,
+        ) * env::storage_byte_cost();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let initial_storage_usage = env::storage_usage();
+        contract.storage_deposit(None, None);
+        contract.internal_create_account(accounts(1), "alice".to_owned());
+
+        // Charged bytes match the bytes written, which are fewer than the worst case
+        let storage_usage = env::storage_usage() - initial_storage_usage;
+        assert!(
+            storage_usage
+                < contract.metadata.user_storage_usage.0
+                    + contract.metadata.account_storage_usage.0
+        );
+        let storage_balance = contract.storage_balance_of(accounts(1)).unwrap();
+        assert_eq!(storage_balance.total.0, deposit);
+        assert_eq!(
+            deposit - storage_balance.available.0,
+            Balance::from(storage_usage) * env::storage_byte_cost()
+        );
+    }
+
+    #[test]
+    fn test_transfer_account_moves_actual_storage() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+        let deposit = Balance::from(
+            contract.metadata.user_storage_usage.0 + contract.metadata.account_storage_usage.0,
+        ) * env::storage_byte_cost();
+
+        for account_id in [accounts(1), accounts(3)] {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(deposit)
+                .predecessor_account_id(account_id)
+                .build());
+            contract.storage_deposit(None, None);
+        }
+        let available = contract.storage_balance_of(accounts(1)).unwrap().available;
+        contract.internal_create_account(accounts(1), "alice".to_owned());
+
+        let old_owner_available = contract.storage_balance_of(accounts(1)).unwrap().available;
+        let new_owner_available = contract.storage_balance_of(accounts(3)).unwrap().available;
+        let initial_storage_usage = env::storage_usage();
+        contract.internal_transfer_account(&"alice".to_owned(), &accounts(3), 0);
+
+        // The previous owner gets back what the account cost them, and the
+        // net charge matches the change in storage usage
+        assert_eq!(
+            contract.storage_balance_of(accounts(1)).unwrap().available,
+            available
+        );
+        let refund = available.0 - old_owner_available.0;
+        let charge = new_owner_available.0
+            - contract
+                .storage_balance_of(accounts(3))
+                .unwrap()
+                .available
+                .0;
+        assert_eq!(
+            charge - refund,
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost()
+        );
+        assert_eq!(
+            contract.get_accounts(accounts(3), None, None),
+            Some(vec!["alice".to_owned()])
+        );
+    }
+
     #[test]
     #[should_panic(expected = "Insufficient deposit to register a user")]
     fn test_storage_deposit_insufficient_deposit() {
@@ -363,7 +681,7 @@ mod test {
             .build());
         contract.storage_unregister(None);
 
-        assert_eq!(contract.user_accounts.get(&tmp_account_id).is_none(), true);
+        assert_eq!(contract.user_accounts.contains_key(&tmp_account_id), false);
         assert_eq!(
             contract
                 .storage_balance_of(tmp_account_id.clone())
@@ -371,7 +689,7 @@ mod test {
             true
         );
         assert_eq!(
-            storage_balance.total.0,
+            storage_balance.total.0 - storage_balance.available.0,
             Balance::from(initial_storage_usage - env::storage_usage()) * env::storage_byte_cost()
         );
     }
@@ -408,13 +726,7 @@ mod test {
         contract.storage_unregister(Some(true));
 
         assert_eq!(contract.accounts.get(&account_name).is_none(), true);
-        assert_eq!(
-            contract
-                .user_accounts
-                .get(&tmp_account_id.clone())
-                .is_none(),
-            true
-        );
+        assert_eq!(contract.user_accounts.contains_key(&tmp_account_id), false);
         assert_eq!(
             contract
                 .storage_balance_of(tmp_account_id.clone())
@@ -422,11 +734,90 @@ mod test {
             true
         );
         assert_eq!(
-            storage_balance.total.0,
+            storage_balance.total.0 - storage_balance.available.0,
             Balance::from(initial_storage_usage - env::storage_usage()) * env::storage_byte_cost()
         );
     }
 
+    #[test]
+    fn test_storage_unregister_user_with_many_accounts() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+        let account_count = MAX_ACCOUNTS_PER_UNREGISTER + 1;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(
+                Balance::from(
+                    contract.metadata.user_storage_usage.0
+                        + contract.metadata.account_storage_usage.0 * account_count
+                ) * env::storage_byte_cost()
+            )
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.storage_deposit(None, None);
+        for index in 0..account_count {
+            contract.internal_create_account(accounts(3), format!("account{}", index));
+        }
+
+        // Accounts beyond what one call removes are removed separately first
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(3))
+            .build());
+        let available = contract.storage_balance_of(accounts(3)).unwrap().available;
+        assert_eq!(contract.remove_user_accounts(1), 1);
+        assert_eq!(
+            contract.user_accounts.len(&accounts(3)),
+            MAX_ACCOUNTS_PER_UNREGISTER
+        );
+        assert!(
+            contract
+                .storage_balance_of(accounts(3))
+                .unwrap()
+                .available
+                .0
+                > available.0
+        );
+
+        assert!(contract.storage_unregister(Some(true)));
+        assert!(!contract.user_accounts.contains_key(&accounts(3)));
+        assert!(contract.storage_balance_of(accounts(3)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many accounts to unregister")]
+    fn test_storage_unregister_too_many_accounts() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+        let account_count = MAX_ACCOUNTS_PER_UNREGISTER + 1;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(
+                Balance::from(
+                    contract.metadata.user_storage_usage.0
+                        + contract.metadata.account_storage_usage.0 * account_count
+                ) * env::storage_byte_cost()
+            )
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.storage_deposit(None, None);
+        for index in 0..account_count {
+            contract.internal_create_account(accounts(3), format!("account{}", index));
+        }
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.storage_unregister(Some(true));
+    }
+
     #[test]
     fn test_storage_balance_bounds() {
         let mut context = get_context(accounts(1));
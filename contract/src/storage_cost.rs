@@ -0,0 +1,508 @@
+use crate::allowance::Allowance;
+use crate::escrow::{Escrow, EscrowStatus};
+use crate::guardian::{Guardian, Recovery};
+use crate::htlc::Htlc;
+use crate::limit::{PendingSpendingLimit, SpendingLimit, WEEK_HOURS};
+use crate::multisig::{Multisig, Proposal, ProposalKind};
+use crate::referral::ReferralStats;
+use crate::sponsor::Sponsorship;
+use crate::standing_order::StandingOrder;
+use crate::vesting::Vesting;
+use crate::{Contract, ContractExt, ACCOUNT_NAME_MAX_LENGTH};
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance, StorageUsage};
+
+// Bytes charged for each storage record on top of its key and value
+const STORAGE_RECORD_OVERHEAD: StorageUsage = 40;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum StorageAction {
+    // storage_deposit of a new user
+    Register,
+
+    // storage_deposit_with_referrer of a new user
+    RegisterWithReferrer,
+
+    // storage_deposit or sponsor_storage_deposit of a new user by another account
+    SponsorRegister,
+
+    // create_account
+    CreateAccount,
+
+    // create_account with a referrer
+    CreateAccountWithReferrer,
+
+    // create_locked_account
+    CreateLockedAccount,
+
+    // approve
+    Approve,
+
+    // set_guardian
+    SetGuardian,
+
+    // start_recovery
+    StartRecovery,
+
+    // set_spending_limit
+    SetSpendingLimit,
+
+    // set_multisig with the given number of signers
+    SetMultisig { signer_count: u32 },
+
+    // create_proposal
+    CreateProposal,
+
+    // confirm_proposal that does not reach the threshold
+    ConfirmProposal,
+
+    // create_escrow
+    CreateEscrow,
+
+    // lock_htlc
+    LockHtlc,
+
+    // create_vesting
+    CreateVesting,
+
+    // create_standing_order, excluding the keeper tips
+    CreateStandingOrder,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get the largest storage cost in yoctoNEAR an action can charge, for the
+    // longest account IDs and account names
+    pub fn get_storage_cost(&self, action: StorageAction) -> U128 {
+        (Balance::from(self.internal_storage_usage(action)) * env::storage_byte_cost()).into()
+    }
+}
+
+impl Contract {
+    fn internal_storage_usage(&self, action: StorageAction) -> StorageUsage {
+        let account_id = AccountId::new_unchecked("a".repeat(64));
+        let account_name = "a".repeat(ACCOUNT_NAME_MAX_LENGTH);
+        match action {
+            StorageAction::Register => self.metadata.user_storage_usage.0,
+            StorageAction::RegisterWithReferrer => {
+                self.metadata.user_storage_usage.0 + referral_storage_usage(&account_id)
+            }
+            StorageAction::SponsorRegister => {
+                self.metadata.user_storage_usage.0
+                    + record_storage_usage(
+                        &account_id,
+                        &Sponsorship {
+                            sponsor_id: account_id.clone(),
+                            amount: 0.into(),
+                            max_accounts: Some(0),
+                        },
+                    )
+            }
+            StorageAction::CreateAccount => self.metadata.account_storage_usage.0,
+            StorageAction::CreateAccountWithReferrer => {
+                self.metadata.account_storage_usage.0 + referral_storage_usage(&account_id)
+            }
+            // Unlock time is added to the account record
+            StorageAction::CreateLockedAccount => {
+                self.metadata.account_storage_usage.0 + borsh_len(&0u64)
+            }
+            StorageAction::Approve => record_storage_usage(
+                &account_name,
+                &vec![Allowance {
+                    spender_id: account_id,
+                    amount: 0.into(),
+                    expires_at: Some(0.into()),
+                }],
+            ),
+            StorageAction::SetGuardian => record_storage_usage(
+                &account_name,
+                &Guardian {
+                    guardian_id: account_id,
+                    frozen: false,
+                    recovery: None,
+                },
+            ),
+            // Recovery is added to the guardian record
+            StorageAction::StartRecovery => borsh_len(&Recovery {
+                new_owner_id: account_id,
+                executable_at: 0.into(),
+            }),
+            StorageAction::SetSpendingLimit => record_storage_usage(
+                &account_name,
+                &SpendingLimit {
+                    daily_limit: Some(0),
+                    weekly_limit: Some(0),
+                    pending_limit: Some(PendingSpendingLimit {
+                        daily_limit: Some(0.into()),
+                        weekly_limit: Some(0.into()),
+                        effective_at: 0.into(),
+                    }),
+                    hourly_outflows: vec![0; WEEK_HOURS as usize],
+                    last_outflow_hour: 0,
+                },
+            ),
+            // Signers are added to the account record
+            StorageAction::SetMultisig { signer_count } => borsh_len(&Multisig {
+                signers: vec![account_id; signer_count as usize],
+                threshold: 0,
+                proposal_lifetime: 0.into(),
+            }),
+            StorageAction::CreateProposal => {
+                record_storage_usage(
+                    &0u64,
+                    &Proposal {
+                        id: 0.into(),
+                        account_name: account_name.clone(),
+                        proposer: account_id.clone(),
+                        kind: ProposalKind::Transfer {
+                            receiver_account_name: account_name.clone(),
+                            amount: 0.into(),
+                        },
+                        confirmations: vec![account_id],
+                        expires_at: 0.into(),
+                    },
+                ) + record_storage_usage(&account_name, &vec![0u64])
+            }
+            // Signer is added to the proposal record
+            StorageAction::ConfirmProposal => borsh_len(&account_id),
+            // Escrow ID is listed under both accounts
+            StorageAction::CreateEscrow => {
+                record_storage_usage(
+                    &0u64,
+                    &Escrow {
+                        id: 0.into(),
+                        from_account: account_name.clone(),
+                        sender_id: account_id.clone(),
+                        to_account_name: account_name.clone(),
+                        amount: 0.into(),
+                        arbiter: account_id,
+                        deadline: 0.into(),
+                        status: EscrowStatus::Pending,
+                    },
+                ) + 2 * record_storage_usage(&account_name, &vec![0u64])
+            }
+            // Open transfers are counted under both accounts
+            StorageAction::LockHtlc => {
+                record_storage_usage(
+                    &0u64,
+                    &Htlc {
+                        id: 0.into(),
+                        sender_account_name: account_name.clone(),
+                        sender_id: account_id,
+                        receiver_account_name: account_name.clone(),
+                        amount: 0.into(),
+                        hash: vec![0; 32].into(),
+                        timeout: 0.into(),
+                    },
+                ) + 2 * record_storage_usage(&account_name, &0u32)
+            }
+            StorageAction::CreateVesting => {
+                record_storage_usage(
+                    &0u64,
+                    &Vesting {
+                        id: 0.into(),
+                        sender_account_name: account_name.clone(),
+                        sender_id: account_id,
+                        receiver_account_name: account_name.clone(),
+                        total: 0.into(),
+                        claimed: 0.into(),
+                        start: 0.into(),
+                        cliff: 0.into(),
+                        duration: 0.into(),
+                    },
+                ) + 2 * record_storage_usage(&account_name, &0u32)
+            }
+            // Unordered map stores an index, a key and a value record under
+            // prefixes one byte longer than its own
+            StorageAction::CreateStandingOrder => {
+                let order = StandingOrder {
+                    id: 0.into(),
+                    owner_id: account_id,
+                    sender_account_name: account_name.clone(),
+                    receiver_account_name: account_name.clone(),
+                    amount: 0.into(),
+                    interval: 0.into(),
+                    remaining_count: 0,
+                    next_execution_at: 0.into(),
+                };
+                record_storage_usage(&0u64, &0u64)
+                    + record_storage_usage(&0u64, &0u64)
+                    + record_storage_usage(&0u64, &order)
+                    + 3
+                    + 2 * record_storage_usage(&account_name, &0u32)
+            }
+        }
+    }
+}
+
+// Storage usage of the referrer of a user and new referral stats of the referrer
+fn referral_storage_usage(account_id: &AccountId) -> StorageUsage {
+    record_storage_usage(account_id, account_id)
+        + record_storage_usage(
+            account_id,
+            &ReferralStats {
+                referred_count: 0,
+                total_reward: 0.into(),
+                available_reward: 0.into(),
+            },
+        )
+}
+
+// Storage usage of a new collection record under a one-byte prefix
+fn record_storage_usage<K: BorshSerialize, V: BorshSerialize>(key: &K, value: &V) -> StorageUsage {
+    STORAGE_RECORD_OVERHEAD + 1 + borsh_len(key) + borsh_len(value)
+}
+
+fn borsh_len<T: BorshSerialize>(value: &T) -> StorageUsage {
+    value.try_to_vec().unwrap().len() as StorageUsage
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::standing_order::KEEPER_TIP;
+    use crate::test::tests::{get_context, new_contract};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    // Build a context for the owner attaching deposit
+    fn set_context(owner_id: &AccountId, deposit: Balance) {
+        testing_env!(get_context(owner_id.clone())
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .build());
+    }
+
+    // Register a user with the longest Account ID owning two accounts with the longest names
+    fn setup_contract() -> (Contract, AccountId, String, String) {
+        let mut contract = new_contract();
+        let owner_id = AccountId::new_unchecked("o".repeat(64));
+        let sender = "s".repeat(ACCOUNT_NAME_MAX_LENGTH);
+        let receiver = "r".repeat(ACCOUNT_NAME_MAX_LENGTH);
+
+        set_context(
+            &owner_id,
+            contract.get_storage_cost(StorageAction::Register).0,
+        );
+        contract.storage_deposit(None, Some(true));
+        for account_name in [&sender, &receiver] {
+            set_context(
+                &owner_id,
+                contract.get_storage_cost(StorageAction::CreateAccount).0,
+            );
+            contract.create_account(account_name.clone(), None);
+        }
+        contract.internal_deposit(sender.clone(), 1_000.into());
+        assert_eq!(
+            contract
+                .storage_balance_of(owner_id.clone())
+                .unwrap()
+                .available,
+            0.into()
+        );
+        (contract, owner_id, sender, receiver)
+    }
+
+    fn storage_total(contract: &Contract, owner_id: &AccountId) -> Balance {
+        contract
+            .storage_balance_of(owner_id.clone())
+            .unwrap()
+            .total
+            .0
+    }
+
+    #[test]
+    fn test_storage_cost_matches_charged_storage() {
+        let (mut contract, owner_id, sender, receiver) = setup_contract();
+        let long_id = |c: &str| AccountId::new_unchecked(c.repeat(64));
+
+        let cost = contract.get_storage_cost(StorageAction::Approve).0;
+        let total = storage_total(&contract, &owner_id);
+        set_context(&owner_id, cost + 1);
+        contract.approve(sender.clone(), long_id("b"), 10.into(), Some(1.into()));
+        assert_eq!(storage_total(&contract, &owner_id) - total, cost);
+
+        let cost = contract.get_storage_cost(StorageAction::SetGuardian).0;
+        let total = storage_total(&contract, &owner_id);
+        set_context(&owner_id, cost + 1);
+        contract.set_guardian(receiver.clone(), long_id("g"));
+        assert_eq!(storage_total(&contract, &owner_id) - total, cost);
+
+        let cost = contract.get_storage_cost(StorageAction::CreateEscrow).0;
+        let total = storage_total(&contract, &owner_id);
+        set_context(&owner_id, cost);
+        contract.create_escrow(
+            sender.clone(),
+            receiver.clone(),
+            10.into(),
+            long_id("e"),
+            1.into(),
+        );
+        assert_eq!(storage_total(&contract, &owner_id) - total, cost);
+
+        let cost = contract.get_storage_cost(StorageAction::LockHtlc).0;
+        let total = storage_total(&contract, &owner_id);
+        set_context(&owner_id, cost);
+        contract.lock_htlc(
+            sender.clone(),
+            receiver.clone(),
+            10.into(),
+            vec![0; 32].into(),
+            1.into(),
+        );
+        assert_eq!(storage_total(&contract, &owner_id) - total, cost);
+    }
+
+    // Registration cost covers the longest Account ID, so only the records added
+    // on top of a plain registration are compared
+    #[test]
+    fn test_registration_storage_cost_matches_charged_storage() {
+        let (mut contract, owner_id, _, _) = setup_contract();
+        let long_id = |c: &str| AccountId::new_unchecked(c.repeat(64));
+        let register_cost = contract.get_storage_cost(StorageAction::Register).0;
+        let charged = |contract: &Contract, account_id: AccountId| {
+            let storage_balance = contract.storage_balance_of(account_id).unwrap();
+            storage_balance.total.0 - storage_balance.available.0
+        };
+
+        set_context(&long_id("t"), register_cost);
+        contract.storage_deposit(None, None);
+        let register_charged = charged(&contract, long_id("t"));
+
+        let cost = contract
+            .get_storage_cost(StorageAction::RegisterWithReferrer)
+            .0;
+        set_context(&long_id("u"), cost);
+        contract.storage_deposit_with_referrer(None, None, owner_id.clone());
+        assert_eq!(
+            charged(&contract, long_id("u")) - register_charged,
+            cost - register_cost
+        );
+
+        let cost = contract.get_storage_cost(StorageAction::SponsorRegister).0;
+        set_context(&owner_id, cost);
+        contract.sponsor_storage_deposit(long_id("v"), Some(1));
+        assert_eq!(
+            charged(&contract, long_id("v")) - register_charged,
+            cost - register_cost
+        );
+
+        let cost = contract
+            .get_storage_cost(StorageAction::CreateAccountWithReferrer)
+            .0;
+        set_context(&long_id("w"), register_cost);
+        contract.storage_deposit(None, Some(true));
+        let total = storage_total(&contract, &long_id("w"));
+        set_context(&long_id("w"), cost);
+        contract.create_account("w".repeat(ACCOUNT_NAME_MAX_LENGTH), Some(long_id("t")));
+        assert_eq!(storage_total(&contract, &long_id("w")) - total, cost);
+    }
+
+    #[test]
+    fn test_create_account_refunds_excess_deposit() {
+        let (mut contract, owner_id, _, _) = setup_contract();
+        let cost = contract.get_storage_cost(StorageAction::CreateAccount).0;
+        let total = storage_total(&contract, &owner_id);
+
+        set_context(&owner_id, cost * 2);
+        contract.create_account("spare".to_owned(), None);
+        assert!(storage_total(&contract, &owner_id) - total <= cost);
+        assert_eq!(
+            contract.storage_balance_of(owner_id).unwrap().available,
+            0.into()
+        );
+    }
+
+    #[test]
+    fn test_confirm_proposal_storage_cost_matches_charged_storage() {
+        let (mut contract, owner_id, sender, receiver) = setup_contract();
+        let signer_id = AccountId::new_unchecked("c".repeat(64));
+        set_context(&owner_id, 10_u128.pow(23));
+        contract.set_multisig(
+            sender.clone(),
+            vec![owner_id.clone(), signer_id.clone(), accounts(3)],
+            3,
+            1.into(),
+        );
+        contract.create_proposal(
+            sender.clone(),
+            ProposalKind::Transfer {
+                receiver_account_name: receiver,
+                amount: 10.into(),
+            },
+        );
+        set_context(
+            &signer_id,
+            contract.get_storage_cost(StorageAction::Register).0,
+        );
+        contract.storage_deposit(None, Some(true));
+
+        let cost = contract.get_storage_cost(StorageAction::ConfirmProposal).0;
+        let total = storage_total(&contract, &signer_id);
+        set_context(&signer_id, cost);
+        contract.confirm_proposal(0.into());
+        assert_eq!(storage_total(&contract, &signer_id) - total, cost);
+
+        // Signer gets the confirmation storage back when the proposal is removed
+        contract.internal_remove_account_proposals(&sender);
+        assert_eq!(
+            contract.storage_balance_of(signer_id).unwrap().available,
+            cost.into()
+        );
+    }
+
+    // Each open transfer is created first, when its counters are new
+    #[test]
+    fn test_vesting_storage_cost_matches_charged_storage() {
+        let (mut contract, owner_id, sender, receiver) = setup_contract();
+        let cost = contract.get_storage_cost(StorageAction::CreateVesting).0;
+        let total = storage_total(&contract, &owner_id);
+        set_context(&owner_id, cost);
+        contract.create_vesting(sender, receiver, 10.into(), 0.into(), 0.into(), 1.into());
+        assert_eq!(storage_total(&contract, &owner_id) - total, cost);
+    }
+
+    #[test]
+    fn test_standing_order_storage_cost_matches_charged_storage() {
+        let (mut contract, owner_id, sender, receiver) = setup_contract();
+        let cost = contract
+            .get_storage_cost(StorageAction::CreateStandingOrder)
+            .0;
+        let total = storage_total(&contract, &owner_id);
+        set_context(&owner_id, cost + KEEPER_TIP);
+        contract.create_standing_order(sender, receiver, 10.into(), 1.into(), 1, None);
+        assert_eq!(storage_total(&contract, &owner_id) - total, cost);
+    }
+
+    #[test]
+    fn test_top_up_refunds_excess_deposit() {
+        let (mut contract, owner_id, sender, _) = setup_contract();
+        let cost = contract.get_storage_cost(StorageAction::SetGuardian).0;
+        let total = storage_total(&contract, &owner_id);
+
+        set_context(&owner_id, cost * 2);
+        let initial_storage_usage = env::storage_usage();
+        contract.set_guardian(sender, accounts(3));
+
+        // Only the storage actually used is kept, the rest is refunded
+        assert_eq!(
+            storage_total(&contract, &owner_id) - total,
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost()
+        );
+        assert_eq!(
+            contract.storage_balance_of(owner_id).unwrap().available,
+            0.into()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage deposit, attach")]
+    fn test_top_up_insufficient_deposit() {
+        let (mut contract, owner_id, sender, _) = setup_contract();
+
+        set_context(&owner_id, 1);
+        contract.set_guardian(sender, accounts(3));
+    }
+}
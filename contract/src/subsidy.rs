@@ -0,0 +1,301 @@
+use crate::role::Role;
+use crate::{Contract, ContractExt};
+use near_contract_standards::storage_management::StorageBalance;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Promise};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageSubsidy {
+    // Tokens charged for registering a user with an account
+    pub registration_fee: U128,
+
+    // NEAR funded by the contract owner
+    pub total: U128,
+
+    // NEAR staked for the storage of subsidized users
+    pub used: U128,
+
+    // Tokens collected as registration fees
+    pub collected_fee: U128,
+
+    // Number of users whose storage is subsidized
+    pub user_count: u64,
+}
+
+impl Default for StorageSubsidy {
+    fn default() -> Self {
+        Self {
+            registration_fee: 0.into(),
+            total: 0.into(),
+            used: 0.into(),
+            collected_fee: 0.into(),
+            user_count: 0,
+        }
+    }
+}
+
+impl StorageSubsidy {
+    // NEAR left for new registrations
+    pub fn available(&self) -> Balance {
+        self.total.0 - self.used.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Add attached NEAR to the storage subsidy pool, called by a token manager
+    #[payable]
+    pub fn fund_storage_subsidy(&mut self) {
+        self.internal_assert_role(Role::TokenManager);
+        require!(env::attached_deposit() > 0, "Attached deposit is required");
+
+        self.storage_subsidy.total = self
+            .storage_subsidy
+            .total
+            .0
+            .checked_add(env::attached_deposit())
+            .unwrap_or_else(|| panic!("Balance overflow"))
+            .into();
+    }
+
+    // Withdraw unused NEAR from the storage subsidy pool to the contract owner,
+    // called by a token manager
+    #[payable]
+    pub fn withdraw_storage_subsidy(&mut self, amount: U128) -> Promise {
+        assert_one_yocto();
+        self.internal_assert_role(Role::TokenManager);
+        require!(
+            amount.0 <= self.storage_subsidy.available(),
+            "Insufficient storage subsidy"
+        );
+
+        self.storage_subsidy.total = (self.storage_subsidy.total.0 - amount.0).into();
+        Promise::new(self.metadata.owner_id.clone()).transfer(amount.0 + 1)
+    }
+
+    // Set tokens charged for registering with the storage subsidy, called by a token manager
+    #[payable]
+    pub fn set_registration_fee(&mut self, registration_fee: U128) {
+        assert_one_yocto();
+        self.internal_assert_role(Role::TokenManager);
+        self.storage_subsidy.registration_fee = registration_fee;
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get accounting of the storage subsidy pool
+    pub fn get_storage_subsidy(&self) -> StorageSubsidy {
+        self.storage_subsidy.clone()
+    }
+}
+
+impl Contract {
+    // Register a user with a new account paying the storage from the subsidy pool
+    // and deposit the tokens left after the registration fee
+    pub fn internal_register_with_token(
+        &mut self,
+        account_id: AccountId,
+        account_name: String,
+        amount: Balance,
+    ) {
+        require!(!self.paused.account_creation, "Account creation is paused");
+        require!(
+            !self.user_accounts.contains_key(&account_id),
+            format!("The user {} is already registered", account_id)
+        );
+        let registration_fee = self.storage_subsidy.registration_fee.0;
+        require!(
+            amount >= registration_fee,
+            "Insufficient amount for the registration fee"
+        );
+        self.internal_assert_new_account_name(&account_name);
+
+        // Pool must cover the largest possible storage of a user with an account
+        let available = self.storage_subsidy.available();
+        require!(
+            available
+                >= Balance::from(
+                    self.metadata.user_storage_usage.0 + self.metadata.account_storage_usage.0
+                ) * env::storage_byte_cost(),
+            "Insufficient storage subsidy"
+        );
+
+        // Lend the pool to the user while the storage is charged
+        let initial_storage_usage = env::storage_usage();
+        self.user_accounts.insert_user(&account_id);
+        self.user_activity
+            .insert(&account_id, &env::block_timestamp());
+        self.storage_subsidies.insert(&account_id, &0);
+        self.storage_balances.insert(
+            &account_id,
+            &StorageBalance {
+                total: available.into(),
+                available: available.into(),
+            },
+        );
+        self.internal_charge_storage(&account_id, initial_storage_usage);
+        self.internal_create_account(account_id.clone(), account_name.clone());
+
+        // Keep only the storage actually used, which returns to the pool on unregister
+        let mut storage_balance = self.storage_balances.get(&account_id).unwrap();
+        let subsidy = storage_balance.total.0 - storage_balance.available.0;
+        storage_balance.total = subsidy.into();
+        storage_balance.available = 0.into();
+        self.storage_balances.insert(&account_id, &storage_balance);
+        self.storage_subsidies.insert(&account_id, &subsidy);
+        self.storage_subsidy.used = (self.storage_subsidy.used.0 + subsidy).into();
+        self.storage_subsidy.user_count += 1;
+
+        // Registration fee is collected with the transfer fees
+        self.storage_subsidy.collected_fee = self
+            .storage_subsidy
+            .collected_fee
+            .0
+            .checked_add(registration_fee)
+            .unwrap_or_else(|| panic!("Balance overflow"))
+            .into();
+        self.total_transfer_fee = self
+            .total_transfer_fee
+            .checked_add(registration_fee)
+            .unwrap_or_else(|| panic!("Balance overflow"));
+        if amount > registration_fee {
+            self.internal_deposit(account_name, (amount - registration_fee).into());
+        }
+    }
+
+    // Return the subsidized storage of an unregistered user to the pool
+    // and get the part of the storage balance paid by the user
+    pub fn internal_release_subsidy(&mut self, account_id: &AccountId, total: Balance) -> Balance {
+        match self.storage_subsidies.remove(account_id) {
+            Some(subsidy) => {
+                self.storage_subsidy.used = (self.storage_subsidy.used.0 - subsidy).into();
+                self.storage_subsidy.user_count -= 1;
+                total - subsidy
+            }
+            None => total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msg::{RegisterPayload, TransferMessage};
+    use crate::test::tests::{get_context, new_contract};
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::{bs58, test_utils::accounts, testing_env};
+
+    const POOL: Balance = 1_000_000_000_000_000_000_000_000;
+
+    fn setup_contract(pool: Balance) -> Contract {
+        let mut contract = new_contract();
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_registration_fee(10.into());
+        if pool > 0 {
+            testing_env!(context.attached_deposit(pool).build());
+            contract.fund_storage_subsidy();
+        }
+        contract
+    }
+
+    fn register(contract: &mut Contract, account_id: AccountId, amount: Balance) {
+        let mut context = get_context(accounts(2));
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        let msg = bs58::encode(
+            (TransferMessage {
+                action: "register".to_owned(),
+                payload: (RegisterPayload {
+                    account_name: "savings".to_owned(),
+                })
+                .try_to_vec()
+                .unwrap(),
+            })
+            .try_to_vec()
+            .unwrap(),
+        )
+        .into_string();
+        contract.ft_on_transfer(account_id, amount.into(), msg);
+    }
+
+    #[test]
+    fn test_register_with_token() {
+        let mut contract = setup_contract(POOL);
+        let initial_storage_usage = env::storage_usage();
+        register(&mut contract, accounts(3), 100);
+
+        assert_eq!(
+            contract.get_account("savings".to_owned()).unwrap().owner_id,
+            accounts(3)
+        );
+        assert_eq!(
+            contract.get_balance("savings".to_owned()).unwrap(),
+            90.into()
+        );
+        assert_eq!(contract.total_transfer_fee, 10);
+
+        // Pool pays exactly the storage used by the user
+        let subsidy =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        let storage_balance = contract.storage_balance_of(accounts(3)).unwrap();
+        assert_eq!(storage_balance.total, subsidy.into());
+        assert_eq!(storage_balance.available, 0.into());
+        assert_eq!(
+            contract.get_storage_subsidy(),
+            StorageSubsidy {
+                registration_fee: 10.into(),
+                total: POOL.into(),
+                used: subsidy.into(),
+                collected_fee: 10.into(),
+                user_count: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_unregister_returns_subsidy() {
+        let mut contract = setup_contract(POOL);
+        register(&mut contract, accounts(3), 10);
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        assert!(contract.storage_unregister(Some(true)));
+
+        let storage_subsidy = contract.get_storage_subsidy();
+        assert_eq!(storage_subsidy.used, 0.into());
+        assert_eq!(storage_subsidy.user_count, 0);
+        assert_eq!(storage_subsidy.available(), POOL);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage subsidy")]
+    fn test_register_without_subsidy() {
+        let mut contract = setup_contract(0);
+        register(&mut contract, accounts(3), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient amount for the registration fee")]
+    fn test_register_below_registration_fee() {
+        let mut contract = setup_contract(POOL);
+        register(&mut contract, accounts(3), 9);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage subsidy")]
+    fn test_withdraw_used_storage_subsidy() {
+        let mut contract = setup_contract(POOL);
+        register(&mut contract, accounts(3), 100);
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_storage_subsidy(POOL.into());
+    }
+}
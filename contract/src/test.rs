@@ -1,14 +1,15 @@
 #[cfg(test)]
 pub mod tests {
     use crate::msg::{DepositPayload, TransferMessage};
-    use crate::{Account, Contract, ContractMetadata};
+    use crate::{
+        Account, Contract, ContractMetadata, TransferQuote, TransferResult, MAX_BATCH_SIZE,
+    };
     use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
-    use near_contract_standards::storage_management::StorageManagement;
     use near_sdk::borsh::BorshSerialize;
     use near_sdk::test_utils::{accounts, VMContextBuilder};
     use near_sdk::{bs58, env, testing_env, AccountId, Balance};
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+    pub fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
         builder
             .current_account_id(accounts(0))
@@ -17,7 +18,32 @@ pub mod tests {
         builder
     }
 
-    fn register_user(contract: &mut Contract, account_id: &AccountId) {
+    pub fn new_contract() -> Contract {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        Contract::new(accounts(1), accounts(2), 1.into(), 100.into())
+    }
+
+    // Register the user with twice the storage of their accounts and create the accounts
+    pub fn setup_user(contract: &mut Contract, account_id: &AccountId, account_names: &[&str]) {
+        let storage_usage = contract.metadata.user_storage_usage.0
+            + contract.metadata.account_storage_usage.0 * account_names.len() as u64;
+        let mut context = get_context(account_id.clone());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(Balance::from(storage_usage) * env::storage_byte_cost() * 2)
+            .build());
+        contract.storage_deposit(None, None);
+        for account_name in account_names {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(0)
+                .build());
+            contract.create_account(account_name.to_string(), None);
+        }
+    }
+
+    pub fn register_user(contract: &mut Contract, account_id: &AccountId) {
         let mut context = get_context(account_id.clone());
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -29,7 +55,7 @@ pub mod tests {
         contract.storage_deposit(Some(account_id.clone()), Some(true));
     }
 
-    fn create_account(contract: &mut Contract, account_id: &AccountId, account_name: &str) {
+    pub fn create_account(contract: &mut Contract, account_id: &AccountId, account_name: &str) {
         let mut context = get_context(account_id.clone());
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -38,7 +64,26 @@ pub mod tests {
             )
             .predecessor_account_id(account_id.clone())
             .build());
-        contract.create_account(account_name.to_owned());
+        contract.create_account(account_name.to_owned(), None);
+    }
+
+    pub fn deposit(contract: &mut Contract, account_name: &str, amount: Balance) {
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        let msg = bs58::encode(
+            (TransferMessage {
+                action: "deposit".to_owned(),
+                payload: (DepositPayload {
+                    account_name: account_name.to_owned(),
+                })
+                .try_to_vec()
+                .unwrap(),
+            })
+            .try_to_vec()
+            .unwrap(),
+        )
+        .into_string();
+        contract.ft_on_transfer(accounts(1), amount.into(), msg);
     }
 
     #[test]
@@ -74,7 +119,9 @@ pub mod tests {
             0.into()
         );
         assert_eq!(
-            contract.get_accounts(accounts(1).clone()).unwrap(),
+            contract
+                .get_accounts(accounts(1).clone(), None, None)
+                .unwrap(),
             vec!["account"]
         );
         assert_eq!(
@@ -84,6 +131,8 @@ pub mod tests {
                 token_id: accounts(2),
                 transfer_fee_numerator: 1.into(),
                 transfer_fee_denominator: 100.into(),
+                referral_fee_numerator: 0.into(),
+                referral_fee_denominator: 1.into(),
                 user_storage_usage: contract.metadata.user_storage_usage,
                 account_storage_usage: contract.metadata.account_storage_usage
             }
@@ -103,14 +152,16 @@ pub mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Insufficient deposit to create an account")]
+    #[should_panic(expected = "Insufficient storage deposit, attach")]
     fn test_create_account_insufficient_deposit() {
         let context = get_context(accounts(1));
         testing_env!(context.build());
         let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
 
         register_user(&mut contract, &accounts(1));
-        contract.create_account("account".to_owned());
+        let mut context = get_context(accounts(1));
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.create_account("account".to_owned(), None);
     }
 
     #[test]
@@ -580,4 +631,209 @@ pub mod tests {
         testing_env!(context.attached_deposit(1).build());
         contract.withdraw_transfer_fee(1.into());
     }
+
+    #[test]
+    #[should_panic(expected = "Cannot transfer to the same account")]
+    fn test_transfer_same_account() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+
+        register_user(&mut contract, &accounts(1));
+        create_account(&mut contract, &accounts(1), "account_1");
+        deposit(&mut contract, "account_1", 1);
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.transfer("account_1".into(), "account_1".into(), 1.into());
+    }
+
+    #[test]
+    fn test_get_transfer_quote() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+
+        register_user(&mut contract, &accounts(1));
+        register_user(&mut contract, &accounts(3));
+        create_account(&mut contract, &accounts(1), "account_1");
+        create_account(&mut contract, &accounts(1), "account_2");
+        create_account(&mut contract, &accounts(3), "account_3");
+        deposit(&mut contract, "account_1", 100);
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.is_view(true).build());
+        assert_eq!(
+            contract.get_transfer_quote("account_1".into(), "account_3".into(), 100.into()),
+            TransferQuote {
+                transfer_fee: 1.into(),
+                received_amount: 99.into(),
+                is_cross_owner: true,
+                sender_balance: 0.into(),
+                receiver_balance: 99.into(),
+                error: None,
+            }
+        );
+        assert_eq!(
+            contract.get_transfer_quote("account_1".into(), "account_2".into(), 100.into()),
+            TransferQuote {
+                transfer_fee: 0.into(),
+                received_amount: 100.into(),
+                is_cross_owner: false,
+                sender_balance: 0.into(),
+                receiver_balance: 100.into(),
+                error: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_transfer_quote_failed_transfer() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+
+        register_user(&mut contract, &accounts(1));
+        create_account(&mut contract, &accounts(1), "account_1");
+        create_account(&mut contract, &accounts(1), "account_2");
+        deposit(&mut contract, "account_1", 1);
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.is_view(true).build());
+        assert_eq!(
+            contract
+                .get_transfer_quote("account_1".into(), "account_2".into(), 2.into())
+                .error,
+            Some("Balance overflow".to_owned())
+        );
+        assert_eq!(
+            contract
+                .get_transfer_quote("account_1".into(), "account_3".into(), 1.into())
+                .error,
+            Some("Receiver account does not exist".to_owned())
+        );
+        assert_eq!(
+            contract
+                .get_transfer_quote("account_3".into(), "account_1".into(), 1.into())
+                .error,
+            Some("Sender account does not exist".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_batch_transfer() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+
+        register_user(&mut contract, &accounts(1));
+        register_user(&mut contract, &accounts(3));
+        create_account(&mut contract, &accounts(1), "account_1");
+        create_account(&mut contract, &accounts(1), "account_2");
+        create_account(&mut contract, &accounts(3), "account_3");
+        deposit(&mut contract, "account_1", 400);
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let results = contract.batch_transfer(
+            "account_1".into(),
+            vec![
+                ("account_2".into(), 100.into()),
+                ("account_3".into(), 100.into()),
+                ("account_3".into(), 200.into()),
+            ],
+        );
+        assert_eq!(
+            results,
+            vec![
+                TransferResult {
+                    receiver_account_name: "account_2".into(),
+                    transfer_fee: 0.into(),
+                    received_amount: 100.into(),
+                },
+                TransferResult {
+                    receiver_account_name: "account_3".into(),
+                    transfer_fee: 1.into(),
+                    received_amount: 99.into(),
+                },
+                TransferResult {
+                    receiver_account_name: "account_3".into(),
+                    transfer_fee: 2.into(),
+                    received_amount: 198.into(),
+                },
+            ]
+        );
+        assert_eq!(
+            contract.get_balance("account_1".to_owned()).unwrap(),
+            0.into()
+        );
+        assert_eq!(
+            contract.get_balance("account_2".to_owned()).unwrap(),
+            100.into()
+        );
+        assert_eq!(
+            contract.get_balance("account_3".to_owned()).unwrap(),
+            297.into()
+        );
+        assert_eq!(contract.total_transfer_fee, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Balance overflow")]
+    fn test_batch_transfer_not_enough_token() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+
+        register_user(&mut contract, &accounts(1));
+        create_account(&mut contract, &accounts(1), "account_1");
+        create_account(&mut contract, &accounts(1), "account_2");
+        deposit(&mut contract, "account_1", 1);
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.batch_transfer(
+            "account_1".into(),
+            vec![
+                ("account_2".into(), 1.into()),
+                ("account_2".into(), 1.into()),
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Receiver account does not exist")]
+    fn test_batch_transfer_non_existent_receiver_account() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+
+        register_user(&mut contract, &accounts(1));
+        create_account(&mut contract, &accounts(1), "account_1");
+        deposit(&mut contract, "account_1", 1);
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.batch_transfer("account_1".into(), vec![("account_2".into(), 1.into())]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many transfers")]
+    fn test_batch_transfer_too_many_transfers() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+
+        register_user(&mut contract, &accounts(1));
+        create_account(&mut contract, &accounts(1), "account_1");
+        create_account(&mut contract, &accounts(1), "account_2");
+        deposit(&mut contract, "account_1", 1000);
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.batch_transfer(
+            "account_1".into(),
+            vec![("account_2".into(), 1.into()); MAX_BATCH_SIZE + 1],
+        );
+    }
 }
@@ -0,0 +1,222 @@
+use crate::account::AccountV1;
+use crate::{Contract, ContractExt, ContractMetadata};
+use near_contract_standards::storage_management::StorageBalance;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Gas, Promise};
+
+// Version of the current contract state layout
+pub const STATE_VERSION: u32 = 2;
+
+// Storage key of the state version, missing for the first layout
+const STATE_VERSION_KEY: &[u8] = b"VERSION";
+
+const GAS_FOR_MIGRATE: Gas = Gas(100_000_000_000_000);
+
+// Contract metadata of the first layout
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractMetadataV1 {
+    pub owner_id: AccountId,
+    pub token_id: AccountId,
+    pub transfer_fee_numerator: U128,
+    pub transfer_fee_denominator: U128,
+    pub user_storage_usage: U64,
+    pub account_storage_usage: U64,
+}
+
+// Contract state of the first layout
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    pub metadata: ContractMetadataV1,
+    pub total_transfer_fee: Balance,
+    pub accounts: LookupMap<String, AccountV1>,
+    pub user_accounts: LookupMap<AccountId, Vec<String>>,
+    pub storage_balances: LookupMap<AccountId, StorageBalance>,
+}
+
+// Contract state in any layout that can be migrated
+pub enum VersionedContract {
+    V1(ContractV1),
+    V2(Box<Contract>),
+}
+
+impl VersionedContract {
+    // Read contract state in the layout given by the stored state version
+    pub fn read() -> Self {
+        match read_state_version() {
+            1 => Self::V1(read_state()),
+            STATE_VERSION => Self::V2(Box::new(read_state())),
+            _ => panic!("Unsupported state version"),
+        }
+    }
+
+    // Convert contract state to the current layout
+    pub fn into_current(self) -> Contract {
+        let mut contract = match self {
+            Self::V1(old) => {
+                let mut contract = Contract::internal_new(ContractMetadata {
+                    owner_id: old.metadata.owner_id,
+                    token_id: old.metadata.token_id,
+                    transfer_fee_numerator: old.metadata.transfer_fee_numerator,
+                    transfer_fee_denominator: old.metadata.transfer_fee_denominator,
+                    referral_fee_numerator: 0.into(),
+                    referral_fee_denominator: 1.into(),
+                    user_storage_usage: old.metadata.user_storage_usage,
+                    account_storage_usage: old.metadata.account_storage_usage,
+                });
+                contract.total_transfer_fee = old.total_transfer_fee;
+                contract
+            }
+            Self::V2(contract) => return *contract,
+        };
+
+        // User and account records grew, so new ones are charged the new size
+        contract.measure_account_storage_usage();
+        contract
+    }
+}
+
+fn read_state<T: BorshDeserialize>() -> T {
+    env::state_read().unwrap_or_else(|| panic!("Contract state not found"))
+}
+
+#[near_bindgen]
+impl Contract {
+    // Deploy new contract code passed as raw input and migrate the state
+    #[payable]
+    pub fn upgrade(&mut self) -> Promise {
+        assert_one_yocto();
+        self.internal_assert_contract_owner();
+        let code = env::input().unwrap_or_else(|| panic!("Contract code is required"));
+
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call("migrate".to_owned(), vec![], 0, GAS_FOR_MIGRATE)
+    }
+
+    // Migrate contract state from any previous layout to the current one
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let contract = VersionedContract::read().into_current();
+        write_state_version();
+        contract
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get version of the contract state layout
+    pub fn get_state_version(&self) -> u32 {
+        read_state_version()
+    }
+}
+
+pub fn read_state_version() -> u32 {
+    env::storage_read(STATE_VERSION_KEY)
+        .map(|value| u32::try_from_slice(&value).unwrap())
+        .unwrap_or(1)
+}
+
+pub fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &STATE_VERSION.try_to_vec().unwrap());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::get_context;
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    // Write contract state as deployed with the first layout
+    fn setup_v1_state() {
+        let mut storage_balances = LookupMap::new(b"s".to_vec());
+        storage_balances.insert(
+            &accounts(1),
+            &StorageBalance {
+                total: 100.into(),
+                available: 10.into(),
+            },
+        );
+        let mut v1_accounts = LookupMap::new(b"a".to_vec());
+        v1_accounts.insert(
+            &"savings".to_owned(),
+            &AccountV1 {
+                owner_id: accounts(1),
+                balance: 50,
+            },
+        );
+        let mut v1_user_accounts = LookupMap::new(b"u".to_vec());
+        v1_user_accounts.insert(&accounts(1), &vec!["savings".to_owned()]);
+        env::state_write(&ContractV1 {
+            metadata: ContractMetadataV1 {
+                owner_id: accounts(1),
+                token_id: accounts(2),
+                transfer_fee_numerator: 1.into(),
+                transfer_fee_denominator: 100.into(),
+                user_storage_usage: 100.into(),
+                account_storage_usage: 200.into(),
+            },
+            total_transfer_fee: 42,
+            accounts: v1_accounts,
+            user_accounts: v1_user_accounts,
+            storage_balances,
+        });
+    }
+
+    #[test]
+    fn test_migrate_from_v1() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        setup_v1_state();
+        assert_eq!(read_state_version(), 1);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.metadata.owner_id, accounts(1));
+        assert_eq!(contract.metadata.transfer_fee_denominator, 100.into());
+        assert_eq!(contract.metadata.referral_fee_denominator, 1.into());
+        assert_eq!(contract.total_transfer_fee, 42);
+        assert_eq!(
+            contract.storage_balances.get(&accounts(1)).unwrap().total,
+            100.into()
+        );
+        assert_eq!(contract.get_paused(), Default::default());
+
+        // Account records keep the first layout until they are written
+        assert_eq!(
+            contract.get_account("savings".to_owned()).unwrap().balance,
+            50.into()
+        );
+        assert_eq!(
+            contract.get_accounts(accounts(1), None, None),
+            Some(vec!["savings".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_migrate_current_state() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+        contract.total_transfer_fee = 42;
+        env::state_write(&contract);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.total_transfer_fee, 42);
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access")]
+    fn test_upgrade_unauthorized_access() {
+        let mut context = get_context(accounts(3));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1), accounts(2), 1.into(), 100.into());
+
+        context.context.input = vec![0];
+        testing_env!(context.attached_deposit(1).build());
+        contract.upgrade();
+    }
+}
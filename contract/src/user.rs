@@ -0,0 +1,226 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedSet};
+use near_sdk::{env, require, AccountId};
+
+// Prefix of the account name set of each user, followed by the hash of the user's Account ID
+const USER_ACCOUNT_SET_PREFIX: &[u8] = b"t";
+
+// Account names owned by each user, with O(1) insert and remove
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct UserAccounts {
+    // User's Account ID -> Set of account names
+    sets: LookupMap<AccountId, UnorderedSet<String>>,
+
+    // User's Account ID -> List of account names, as stored before sets were used.
+    // A list is never moved as a whole, since a long one would not fit in one call.
+    // New accounts go to the set and the list shrinks as its accounts are removed.
+    legacy: LookupMap<AccountId, Vec<String>>,
+}
+
+impl UserAccounts {
+    pub fn new(prefix: Vec<u8>, legacy_prefix: Vec<u8>) -> Self {
+        Self {
+            sets: LookupMap::new(prefix),
+            legacy: LookupMap::new(legacy_prefix),
+        }
+    }
+
+    // Whether the user is registered
+    pub fn contains_key(&self, account_id: &AccountId) -> bool {
+        self.sets.contains_key(account_id) || self.legacy.contains_key(account_id)
+    }
+
+    // Get the number of accounts of a registered user
+    pub fn len(&self, account_id: &AccountId) -> u64 {
+        let legacy_len = self
+            .legacy
+            .get(account_id)
+            .map_or(0, |accounts| accounts.len() as u64);
+        legacy_len + self.sets.get(account_id).map_or(0, |set| set.len())
+    }
+
+    // Get up to limit account names of a registered user, starting at from_index
+    pub fn get(&self, account_id: &AccountId, from_index: u64, limit: u64) -> Option<Vec<String>> {
+        if !self.contains_key(account_id) {
+            return None;
+        }
+        let legacy = self.legacy.get(account_id).unwrap_or_default();
+        let set = self.sets.get(account_id);
+        Some(
+            legacy
+                .into_iter()
+                .chain(set.iter().flat_map(|set| set.as_vector().iter()))
+                .skip(from_index as usize)
+                .take(limit as usize)
+                .collect(),
+        )
+    }
+
+    // Register a user without accounts
+    pub fn insert_user(&mut self, account_id: &AccountId) {
+        self.sets.insert(account_id, &new_set(account_id));
+    }
+
+    // Unregister a user whose accounts have all been removed
+    pub fn remove(&mut self, account_id: &AccountId) {
+        require!(self.len(account_id) == 0, "The user still has accounts");
+        self.sets.remove(account_id);
+        self.legacy.remove(account_id);
+    }
+
+    // Remove up to limit account names from a registered user and return them,
+    // draining the legacy list first
+    pub fn remove_accounts(&mut self, account_id: &AccountId, limit: u64) -> Vec<String> {
+        let mut accounts = Vec::new();
+        if let Some(mut legacy) = self.legacy.get(account_id) {
+            accounts = legacy.split_off(legacy.len().saturating_sub(limit as usize));
+            if legacy.is_empty() {
+                self.legacy.remove(account_id);
+                // Legacy user keeps being registered through an empty set
+                if !self.sets.contains_key(account_id) {
+                    self.sets.insert(account_id, &new_set(account_id));
+                }
+            } else {
+                self.legacy.insert(account_id, &legacy);
+            }
+        }
+
+        let limit = limit - accounts.len() as u64;
+        if let Some(mut set) = self.sets.get(account_id).filter(|_| limit > 0) {
+            let set_accounts: Vec<String> = set
+                .as_vector()
+                .iter()
+                .skip(set.len().saturating_sub(limit) as usize)
+                .collect();
+            for account_name in set_accounts.iter() {
+                set.remove(account_name);
+            }
+            self.sets.insert(account_id, &set);
+            accounts.extend(set_accounts);
+        }
+        accounts
+    }
+
+    // Add an account name to a registered user
+    pub fn insert_account(&mut self, account_id: &AccountId, account_name: &String) {
+        require!(
+            self.contains_key(account_id),
+            format!("The user {} is not registered", account_id)
+        );
+        let mut set = self
+            .sets
+            .get(account_id)
+            .unwrap_or_else(|| new_set(account_id));
+        set.insert(account_name);
+        self.sets.insert(account_id, &set);
+    }
+
+    // Remove an account name from a registered user
+    pub fn remove_account(&mut self, account_id: &AccountId, account_name: &String) {
+        if let Some(mut set) = self.sets.get(account_id) {
+            if set.remove(account_name) {
+                self.sets.insert(account_id, &set);
+                return;
+            }
+        }
+        if let Some(mut legacy) = self.legacy.get(account_id) {
+            legacy.retain(|name| name != account_name);
+            self.legacy.insert(account_id, &legacy);
+        }
+    }
+}
+
+fn new_set(account_id: &AccountId) -> UnorderedSet<String> {
+    let mut prefix = USER_ACCOUNT_SET_PREFIX.to_vec();
+    prefix.extend(env::sha256(account_id.as_bytes()));
+    UnorderedSet::new(prefix)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_legacy_user_accounts() {
+        let mut user_accounts = UserAccounts::new(b"n".to_vec(), b"u".to_vec());
+        user_accounts
+            .legacy
+            .insert(&accounts(1), &vec!["alice".to_owned(), "bob".to_owned()]);
+        assert!(user_accounts.contains_key(&accounts(1)));
+
+        // New accounts go to the set next to the legacy list
+        user_accounts.insert_account(&accounts(1), &"carol".to_owned());
+        user_accounts.remove_account(&accounts(1), &"alice".to_owned());
+        assert_eq!(
+            user_accounts.get(&accounts(1), 0, u64::MAX),
+            Some(vec!["bob".to_owned(), "carol".to_owned()])
+        );
+        assert_eq!(
+            user_accounts.get(&accounts(1), 1, 1),
+            Some(vec!["carol".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_remove_large_legacy_user() {
+        let mut user_accounts = UserAccounts::new(b"n".to_vec(), b"u".to_vec());
+        let names: Vec<String> = (0..5_000)
+            .map(|index| format!("account{}", index))
+            .collect();
+        user_accounts.legacy.insert(&accounts(1), &names);
+
+        // Writing a legacy user leaves the long list where it is
+        user_accounts.insert_account(&accounts(1), &"alice".to_owned());
+        assert_eq!(user_accounts.legacy.get(&accounts(1)).unwrap().len(), 5_000);
+        assert_eq!(user_accounts.len(&accounts(1)), 5_001);
+
+        // The list is drained in chunks before the set, each within one call's gas
+        while user_accounts.len(&accounts(1)) > 1 {
+            testing_env!(VMContextBuilder::new().build());
+            assert_eq!(user_accounts.remove_accounts(&accounts(1), 100).len(), 100);
+        }
+        assert!(user_accounts.legacy.get(&accounts(1)).is_none());
+        assert_eq!(
+            user_accounts.remove_accounts(&accounts(1), 100),
+            vec!["alice".to_owned()]
+        );
+        user_accounts.remove(&accounts(1));
+        assert!(!user_accounts.contains_key(&accounts(1)));
+    }
+
+    #[test]
+    fn test_remove_user() {
+        let mut user_accounts = UserAccounts::new(b"n".to_vec(), b"u".to_vec());
+        user_accounts.insert_user(&accounts(1));
+        user_accounts.insert_user(&accounts(2));
+        for account_name in ["alice", "bob", "carol"] {
+            user_accounts.insert_account(&accounts(1), &account_name.to_owned());
+        }
+        user_accounts.insert_account(&accounts(2), &"dave".to_owned());
+
+        // Accounts are removed in chunks before the user
+        assert_eq!(user_accounts.remove_accounts(&accounts(1), 2).len(), 2);
+        assert_eq!(user_accounts.len(&accounts(1)), 1);
+        assert_eq!(
+            user_accounts.remove_accounts(&accounts(1), 2),
+            vec!["alice".to_owned()]
+        );
+        user_accounts.remove(&accounts(1));
+        assert!(!user_accounts.contains_key(&accounts(1)));
+        assert_eq!(
+            user_accounts.get(&accounts(2), 0, u64::MAX),
+            Some(vec!["dave".to_owned()])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The user still has accounts")]
+    fn test_remove_user_with_accounts() {
+        let mut user_accounts = UserAccounts::new(b"n".to_vec(), b"u".to_vec());
+        user_accounts.insert_user(&accounts(1));
+        user_accounts.insert_account(&accounts(1), &"alice".to_owned());
+        user_accounts.remove(&accounts(1));
+    }
+}
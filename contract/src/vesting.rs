@@ -0,0 +1,324 @@
+use crate::{Contract, ContractExt};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Vesting {
+    pub id: U64,
+
+    // Account the tokens were escrowed from
+    pub sender_account_name: String,
+
+    // Owner of the sender account who paid for the vesting storage
+    pub sender_id: AccountId,
+
+    // Account the vested tokens are claimed into
+    pub receiver_account_name: String,
+
+    // Total amount escrowed for the vesting
+    pub total: U128,
+
+    // Amount already paid out to the receiver
+    pub claimed: U128,
+
+    // Timestamp in nanoseconds when vesting starts
+    pub start: U64,
+
+    // Duration in nanoseconds after start before any tokens vest
+    pub cliff: U64,
+
+    // Duration in nanoseconds after start until all tokens are vested
+    pub duration: U64,
+}
+
+impl Vesting {
+    // Amount vested at the given timestamp
+    pub fn vested_amount(&self, timestamp: u64) -> Balance {
+        let elapsed = timestamp.saturating_sub(self.start.0);
+        if elapsed < self.cliff.0 {
+            return 0;
+        }
+        if elapsed >= self.duration.0 {
+            return self.total.0;
+        }
+
+        // Split the multiplication to avoid overflowing large totals
+        let (elapsed, duration) = (Balance::from(elapsed), Balance::from(self.duration.0));
+        self.total.0 / duration * elapsed + self.total.0 % duration * elapsed / duration
+    }
+
+    // Amount vested but not yet claimed at the given timestamp
+    pub fn claimable_amount(&self, timestamp: u64) -> Balance {
+        self.vested_amount(timestamp) - self.claimed.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Escrow tokens from sender account that vest linearly to receiver account
+    #[payable]
+    pub fn create_vesting(
+        &mut self,
+        sender_account_name: String,
+        receiver_account_name: String,
+        total: U128,
+        start: U64,
+        cliff: U64,
+        duration: U64,
+    ) -> U64 {
+        require!(!self.paused.transfers, "Transfers are paused");
+        require!(total.0 > 0, "Vesting total must be positive");
+        require!(duration.0 > 0, "Vesting duration must be positive");
+        require!(
+            cliff.0 <= duration.0,
+            "Vesting cliff cannot exceed its duration"
+        );
+        require!(
+            self.accounts.get(&receiver_account_name).is_some(),
+            "Receiver account does not exist"
+        );
+        require!(
+            sender_account_name != receiver_account_name,
+            "Cannot transfer to the same account"
+        );
+
+        // Escrow the total from sender account
+        self.internal_withdraw_from_account(&sender_account_name, total.into());
+
+        // Sender pays for the vesting storage
+        let initial_storage_usage = env::storage_usage();
+        let id = self.next_vesting_id;
+        self.next_vesting_id += 1;
+        let vesting = Vesting {
+            id: id.into(),
+            sender_account_name,
+            sender_id: env::signer_account_id(),
+            receiver_account_name,
+            total,
+            claimed: 0.into(),
+            start,
+            cliff,
+            duration,
+        };
+        self.vestings.insert(&id, &vesting);
+        self.internal_add_open_transfer(
+            &vesting.sender_account_name,
+            &vesting.receiver_account_name,
+        );
+        self.internal_charge_storage_with_deposit(
+            &vesting.sender_id,
+            initial_storage_usage,
+            env::attached_deposit(),
+        );
+        id.into()
+    }
+
+    // Claim vested tokens into the receiver account
+    pub fn claim_vesting(&mut self, vesting_id: U64) -> U128 {
+        require!(!self.paused.transfers, "Transfers are paused");
+        let mut vesting = self
+            .vestings
+            .get(&vesting_id.0)
+            .unwrap_or_else(|| panic!("Vesting does not exist"));
+        let receiver_account = self
+            .accounts
+            .get(&vesting.receiver_account_name)
+            .unwrap_or_else(|| panic!("Receiver account does not exist"));
+        require!(
+            receiver_account.owner_id == env::signer_account_id(),
+            "Unauthorized access to account"
+        );
+
+        let amount = vesting.claimable_amount(env::block_timestamp());
+        require!(amount > 0, "Nothing to claim");
+        self.internal_pay_vesting(&vesting, amount);
+
+        // Fully claimed vesting is removed to free its storage
+        vesting.claimed = (vesting.claimed.0 + amount).into();
+        if vesting.claimed == vesting.total {
+            self.internal_remove_vesting(&vesting);
+        } else {
+            self.vestings.insert(&vesting_id.0, &vesting);
+        }
+        amount.into()
+    }
+
+    // Cancel vesting, paying out the vested part and returning the rest to sender
+    pub fn cancel_vesting(&mut self, vesting_id: U64) -> U128 {
+        require!(!self.paused.transfers, "Transfers are paused");
+        let vesting = self
+            .vestings
+            .get(&vesting_id.0)
+            .unwrap_or_else(|| panic!("Vesting does not exist"));
+        require!(
+            vesting.sender_id == env::signer_account_id(),
+            "Unauthorized access to vesting"
+        );
+
+        let vested_amount = vesting.claimable_amount(env::block_timestamp());
+        let mut unvested_amount = vesting.total.0 - vesting.claimed.0 - vested_amount;
+        if self.accounts.get(&vesting.receiver_account_name).is_some() {
+            if vested_amount > 0 {
+                self.internal_pay_vesting(&vesting, vested_amount);
+            }
+        } else {
+            unvested_amount += vested_amount;
+        }
+
+        if unvested_amount > 0 {
+            self.internal_refund(&vesting.sender_account_name, unvested_amount);
+        }
+        self.internal_remove_vesting(&vesting);
+        unvested_amount.into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Get vesting by ID
+    pub fn get_vesting(&self, vesting_id: U64) -> Option<Vesting> {
+        self.vestings.get(&vesting_id.0)
+    }
+
+    // Get amount the receiver can currently claim from a vesting
+    pub fn get_claimable_vesting(&self, vesting_id: U64) -> U128 {
+        self.vestings
+            .get(&vesting_id.0)
+            .map(|vesting| vesting.claimable_amount(env::block_timestamp()))
+            .unwrap_or(0)
+            .into()
+    }
+}
+
+impl Contract {
+    // Credit escrowed tokens to the receiver account, charging the transfer fee
+    fn internal_pay_vesting(&mut self, vesting: &Vesting, amount: Balance) {
+        let mut receiver_account = self
+            .accounts
+            .get(&vesting.receiver_account_name)
+            .unwrap_or_else(|| panic!("Receiver account does not exist"));
+        let transfer_fee = self
+            .internal_credit_transfer(&vesting.sender_id, &mut receiver_account, amount)
+            .unwrap_or_else(|err| panic!("{}", err));
+        self.internal_collect_transfer_fee(&vesting.sender_id, transfer_fee);
+        self.accounts
+            .insert(&vesting.receiver_account_name, &receiver_account);
+    }
+
+    // Remove vesting and refund its storage to the sender
+    fn internal_remove_vesting(&mut self, vesting: &Vesting) {
+        let initial_storage_usage = env::storage_usage();
+        self.vestings.remove(&vesting.id.0);
+        self.internal_remove_open_transfer(
+            &vesting.sender_account_name,
+            &vesting.receiver_account_name,
+        );
+        self.internal_refund_storage(&vesting.sender_id, initial_storage_usage);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{test_utils::accounts, testing_env};
+
+    const START: u64 = 1_000;
+    const CLIFF: u64 = 250;
+    const DURATION: u64 = 1_000;
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        for account_id in [accounts(1), accounts(3)] {
+            setup_user(&mut contract, &account_id, &[account_id.as_str()]);
+        }
+        contract.internal_deposit(accounts(1).to_string(), 1_000.into());
+
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        contract.create_vesting(
+            accounts(1).to_string(),
+            accounts(3).to_string(),
+            1_000.into(),
+            START.into(),
+            CLIFF.into(),
+            DURATION.into(),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_create_vesting() {
+        let contract = setup_contract();
+
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            0.into()
+        );
+        let vesting = contract.get_vesting(0.into()).unwrap();
+        assert_eq!(vesting.vested_amount(START + CLIFF - 1), 0);
+        assert_eq!(vesting.vested_amount(START + CLIFF), 250);
+        assert_eq!(vesting.vested_amount(START + DURATION), 1_000);
+    }
+
+    #[test]
+    fn test_claim_vesting() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.block_timestamp(START + 500).build());
+        assert_eq!(contract.claim_vesting(0.into()), 500.into());
+
+        // Receiver has a different owner so the transfer fee is charged
+        assert_eq!(
+            contract.get_balance(accounts(3).to_string()).unwrap(),
+            495.into()
+        );
+        assert_eq!(contract.total_transfer_fee, 5);
+
+        testing_env!(context.block_timestamp(START + DURATION).build());
+        assert_eq!(contract.claim_vesting(0.into()), 500.into());
+        assert!(contract.get_vesting(0.into()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Nothing to claim")]
+    fn test_claim_vesting_before_cliff() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.block_timestamp(START + CLIFF - 1).build());
+        contract.claim_vesting(0.into());
+    }
+
+    #[test]
+    fn test_cancel_vesting() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(START + 400).build());
+        assert_eq!(contract.cancel_vesting(0.into()), 600.into());
+        assert_eq!(
+            contract.get_balance(accounts(1).to_string()).unwrap(),
+            600.into()
+        );
+        assert_eq!(
+            contract.get_balance(accounts(3).to_string()).unwrap(),
+            396.into()
+        );
+        assert!(contract.get_vesting(0.into()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access to vesting")]
+    fn test_cancel_vesting_unauthorized_access() {
+        let mut contract = setup_contract();
+
+        let context = get_context(accounts(3));
+        testing_env!(context.build());
+        contract.cancel_vesting(0.into());
+    }
+}
@@ -0,0 +1,449 @@
+use crate::{Contract, ContractExt};
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, Gas, Promise,
+    PromiseResult,
+};
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(35_000_000_000_000);
+const GAS_FOR_RESOLVE_WITHDRAW: Gas = Gas(5_000_000_000_000);
+
+// Maximum number of payouts in a batch withdrawal, each needing gas for a transfer and its callback
+const MAX_BATCH_WITHDRAW_SIZE: usize = 10;
+
+#[near_bindgen]
+impl Contract {
+    // Withdraw tokens from account to another NEAR account
+    #[payable]
+    pub fn withdraw_to(
+        &mut self,
+        account_name: String,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) -> Promise {
+        assert_one_yocto();
+        self.internal_withdraw_from_account(&account_name, amount.into());
+        self.internal_ft_transfer(account_name, receiver_id, amount, memo)
+    }
+
+    // Withdraw tokens from account to multiple NEAR accounts
+    #[payable]
+    pub fn batch_withdraw(
+        &mut self,
+        account_name: String,
+        withdrawals: Vec<(AccountId, U128, Option<String>)>,
+    ) {
+        assert_one_yocto();
+        require!(!withdrawals.is_empty(), "No withdrawals to execute");
+        require!(
+            withdrawals.len() <= MAX_BATCH_WITHDRAW_SIZE,
+            "Too many withdrawals"
+        );
+
+        // Subtract the total amount from account balance at once
+        let total_amount = withdrawals
+            .iter()
+            .try_fold(0, |total: Balance, (_, amount, _)| {
+                total.checked_add(amount.0)
+            })
+            .unwrap_or_else(|| panic!("Balance overflow"));
+        self.internal_withdraw_from_account(&account_name, total_amount);
+
+        // Each payout is refunded independently if it fails
+        for (receiver_id, amount, memo) in withdrawals {
+            self.internal_ft_transfer(account_name.clone(), receiver_id, amount, memo);
+        }
+    }
+
+    // Withdraw tokens from account into another contract with ft_transfer_call
+    #[payable]
+    pub fn withdraw_call(
+        &mut self,
+        account_name: String,
+        receiver_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> Promise {
+        assert_one_yocto();
+        require!(!self.paused.withdrawals, "Withdrawals are paused");
+        self.internal_withdraw_from_account(&account_name, amount.into());
+        self.internal_add_pending_withdrawal(&account_name);
+
+        ext_ft_core::ext(self.metadata.token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
+            .ft_transfer_call(receiver_id, amount, None, msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_WITHDRAW)
+                    .resolve_withdraw_call(account_name, amount),
+            )
+    }
+
+    // Refund the account if the token transfer failed
+    #[private]
+    pub fn resolve_withdraw(&mut self, account_name: String, amount: U128) -> U128 {
+        self.internal_remove_pending_withdrawal(&account_name);
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => 0.into(),
+            _ => {
+                self.internal_refund(&account_name, amount.into());
+                amount
+            }
+        }
+    }
+
+    // Refund the account with the amount the receiver did not use
+    #[private]
+    pub fn resolve_withdraw_call(&mut self, account_name: String, amount: U128) -> U128 {
+        self.internal_remove_pending_withdrawal(&account_name);
+        let unused_amount = match env::promise_result(0) {
+            // Token contract returns the amount used by the receiver
+            PromiseResult::Successful(value) => {
+                match near_sdk::serde_json::from_slice::<U128>(&value) {
+                    Ok(used_amount) => amount.0.saturating_sub(used_amount.0),
+                    Err(_) => 0,
+                }
+            }
+            _ => amount.0,
+        };
+
+        if unused_amount > 0 {
+            self.internal_refund(&account_name, unused_amount);
+        }
+        unused_amount.into()
+    }
+}
+
+impl Contract {
+    // Subtract amount from an account owned by the caller
+    pub fn internal_withdraw_from_account(&mut self, account_name: &String, amount: Balance) {
+        require!(
+            self.user_accounts.contains_key(&env::signer_account_id()),
+            format!("The user {} is not registered", env::signer_account_id())
+        );
+
+        // Get account by account name
+        let mut account = self
+            .accounts
+            .get(account_name)
+            .unwrap_or_else(|| panic!("Account does not exist"));
+
+        // Check if account owner is the same as the caller
+        require!(
+            account.owner_id == env::signer_account_id(),
+            "Unauthorized access to account"
+        );
+        require!(
+            account.multisig.is_none(),
+            "Multi-signature account requires a proposal"
+        );
+        self.internal_before_outflow(account_name, &account, amount);
+
+        // Subtract amount from account balance
+        account.balance = account
+            .balance
+            .checked_sub(amount)
+            .unwrap_or_else(|| panic!("Balance overflow"));
+        self.accounts.insert(account_name, &account);
+    }
+
+    // Call token contract to transfer tokens and refund the account on failure
+    pub fn internal_ft_transfer(
+        &mut self,
+        account_name: String,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) -> Promise {
+        require!(!self.paused.withdrawals, "Withdrawals are paused");
+        self.internal_add_pending_withdrawal(&account_name);
+        ext_ft_core::ext(self.metadata.token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(receiver_id, amount, memo)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_WITHDRAW)
+                    .resolve_withdraw(account_name, amount),
+            )
+    }
+
+    // Count a withdrawal whose refund needs the account to still exist
+    fn internal_add_pending_withdrawal(&mut self, account_name: &String) {
+        let count = self.pending_withdrawals.get(account_name).unwrap_or(0);
+        self.pending_withdrawals.insert(account_name, &(count + 1));
+    }
+
+    fn internal_remove_pending_withdrawal(&mut self, account_name: &String) {
+        match self.pending_withdrawals.get(account_name) {
+            Some(count) if count > 1 => {
+                self.pending_withdrawals.insert(account_name, &(count - 1));
+            }
+            _ => {
+                self.pending_withdrawals.remove(account_name);
+            }
+        }
+    }
+
+    // Whether a withdrawal from the account may still be refunded
+    pub fn internal_has_pending_withdrawals(&self, account_name: &String) -> bool {
+        self.pending_withdrawals.contains_key(account_name)
+    }
+
+    // Add refunded tokens back to the account
+    pub fn internal_refund(&mut self, account_name: &String, amount: Balance) {
+        match self.accounts.get(account_name) {
+            Some(mut account) => {
+                account.balance = account
+                    .balance
+                    .checked_add(amount)
+                    .unwrap_or_else(|| panic!("Balance overflow"));
+                self.accounts.insert(account_name, &account);
+            }
+            None => {
+                // Accounts with pending withdrawals cannot be removed, so this
+                // only happens to withdrawals started before they were counted
+                log!(
+                    "Account {} does not exist, adding refund to transfer fee",
+                    account_name
+                );
+                self.total_transfer_fee = self
+                    .total_transfer_fee
+                    .checked_add(amount)
+                    .unwrap_or_else(|| panic!("Balance overflow"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::tests::{get_context, new_contract, setup_user};
+    use near_sdk::{test_utils::accounts, testing_env, RuntimeFeesConfig, VMConfig};
+
+    fn setup_contract() -> Contract {
+        let mut contract = new_contract();
+        setup_user(&mut contract, &accounts(1), &["account"]);
+        contract.internal_deposit("account".to_owned(), 100.into());
+        contract
+    }
+
+    #[test]
+    fn test_withdraw_to() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_to("account".to_owned(), accounts(3), 40.into(), None);
+        assert_eq!(
+            contract.get_balance("account".to_owned()).unwrap(),
+            60.into()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized access to account")]
+    fn test_withdraw_to_unauthorized_access() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(
+                Balance::from(contract.metadata.user_storage_usage.0) * env::storage_byte_cost()
+            )
+            .build());
+        contract.storage_deposit(None, Some(true));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_to("account".to_owned(), accounts(3), 40.into(), None);
+    }
+
+    #[test]
+    fn test_batch_withdraw() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.batch_withdraw(
+            "account".to_owned(),
+            vec![
+                (accounts(3), 40.into(), None),
+                (accounts(4), 50.into(), Some("payroll".to_owned())),
+            ],
+        );
+        assert_eq!(
+            contract.get_balance("account".to_owned()).unwrap(),
+            10.into()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Balance overflow")]
+    fn test_batch_withdraw_not_enough_token() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.batch_withdraw(
+            "account".to_owned(),
+            vec![
+                (accounts(3), 60.into(), None),
+                (accounts(4), 50.into(), None),
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many withdrawals")]
+    fn test_batch_withdraw_too_many_withdrawals() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.batch_withdraw(
+            "account".to_owned(),
+            vec![(accounts(3), 1.into(), None); MAX_BATCH_WITHDRAW_SIZE + 1],
+        );
+    }
+
+    #[test]
+    fn test_resolve_withdraw() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_to("account".to_owned(), accounts(3), 40.into(), None);
+
+        // Successful transfer is not refunded
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        assert_eq!(
+            contract.resolve_withdraw("account".to_owned(), 40.into()),
+            0.into()
+        );
+        assert_eq!(
+            contract.get_balance("account".to_owned()).unwrap(),
+            60.into()
+        );
+
+        // Failed transfer is refunded
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        assert_eq!(
+            contract.resolve_withdraw("account".to_owned(), 40.into()),
+            40.into()
+        );
+        assert_eq!(
+            contract.get_balance("account".to_owned()).unwrap(),
+            100.into()
+        );
+    }
+
+    #[test]
+    fn test_unregister_with_pending_withdrawal() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_to("account".to_owned(), accounts(3), 100.into(), None);
+        assert!(contract.internal_has_pending_withdrawals(&"account".to_owned()));
+
+        // Account is kept until the refund can no longer happen
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        contract.resolve_withdraw("account".to_owned(), 100.into());
+        assert!(!contract.internal_has_pending_withdrawals(&"account".to_owned()));
+
+        testing_env!(context.attached_deposit(1).build());
+        assert!(contract.storage_unregister(Some(true)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot unregister the user with pending withdrawals")]
+    fn test_unregister_before_withdrawal_resolved() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_to("account".to_owned(), accounts(3), 100.into(), None);
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    fn test_withdraw_call() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_call("account".to_owned(), accounts(3), 40.into(), "".to_owned());
+        assert_eq!(
+            contract.get_balance("account".to_owned()).unwrap(),
+            60.into()
+        );
+
+        // Receiver used only part of the tokens
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                near_sdk::serde_json::to_vec(&U128(30)).unwrap()
+            )],
+        );
+        assert_eq!(
+            contract.resolve_withdraw_call("account".to_owned(), 40.into()),
+            10.into()
+        );
+        assert_eq!(
+            contract.get_balance("account".to_owned()).unwrap(),
+            70.into()
+        );
+    }
+
+    #[test]
+    fn test_resolve_withdraw_call_failed() {
+        let mut contract = setup_contract();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_call("account".to_owned(), accounts(3), 40.into(), "".to_owned());
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        assert_eq!(
+            contract.resolve_withdraw_call("account".to_owned(), 40.into()),
+            40.into()
+        );
+        assert_eq!(
+            contract.get_balance("account".to_owned()).unwrap(),
+            100.into()
+        );
+    }
+}
@@ -10,10 +10,21 @@ export interface VaultContractMetadata {
   token_id: string
   transfer_fee_numerator: string
   transfer_fee_denominator: string
+  referral_fee_numerator: string
+  referral_fee_denominator: string
   user_storage_usage: string
   account_storage_usage: string
 }
 
+export interface TransferQuote {
+  transfer_fee: string
+  received_amount: string
+  is_cross_owner: boolean
+  sender_balance: string
+  receiver_balance: string
+  error: string | null
+}
+
 export interface StorageBalanceBounds {
   min: string
   max: string | null
@@ -23,6 +34,7 @@ interface VaultChangeContract extends Contract {
   create_account: (
     args: {
       account_name: string
+      referrer_id?: string
     },
     gas?: string
   ) => Promise<null>
@@ -66,6 +78,11 @@ interface VaultViewContract extends Contract {
   get_metadata: () => Promise<VaultContractMetadata>
   get_accounts: (args: { account_id: string }) => Promise<string[] | null>
   get_balance: (args: { account_name: string }) => Promise<string | null>
+  get_transfer_quote: (args: {
+    sender_account_name: string
+    receiver_account_name: string
+    amount: string
+  }) => Promise<TransferQuote>
   storage_balance_bounds: () => Promise<StorageBalanceBounds>
   storage_balance_of: (args: {
     account_id: string
@@ -88,6 +105,7 @@ const vaultContract = (wallet: ConnectedWalletAccount) => {
       'get_metadata',
       'get_accounts',
       'get_balance',
+      'get_transfer_quote',
       'storage_balance_bounds',
       'storage_balance_of',
     ],